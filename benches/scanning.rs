@@ -0,0 +1,49 @@
+//! Compares scanning ASCII source through the byte fast path and through grapheme
+//! segmentation. Run with `cargo bench --bench scanning`.
+
+use std::time::{Duration, Instant};
+
+use rlox::lexing::Scanner;
+
+const ITERATIONS: u32 = 20;
+
+fn corpus() -> String {
+    let chunk = "print 1 + 2.5 * (3 - 0x1F) ** 2; // arithmetic\n\
+                 print [1, 2, 3][1] ?? {\"key\": \"value\"}[\"key\"];\n\
+                 /* a block comment */ print \"a string literal\" + \"another\";\n";
+    chunk.repeat(2_000)
+}
+
+fn time(source: &str, scanner: fn(&str) -> Scanner) -> Duration {
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        scanner(source).scan().unwrap();
+    }
+    start.elapsed() / ITERATIONS
+}
+
+fn main() {
+    let source = corpus();
+    let ascii = time(&source, |source| Scanner::new(source));
+    let graphemes = time(&source, |source| {
+        Scanner::new(source).without_ascii_fast_path()
+    });
+    let mib = source.len() as f64 / (1024.0 * 1024.0);
+    println!(
+        "scanning {:.2} MiB of ASCII source, {} runs each",
+        mib, ITERATIONS
+    );
+    for (name, duration) in &[("ascii fast path", ascii), ("graphemes", graphemes)] {
+        println!(
+            "{:>16}: {:>10.2?} per run, {:>7.2} MiB/s",
+            name,
+            duration,
+            mib / duration.as_secs_f64()
+        );
+    }
+    println!(
+        "{:>16}: {:.2}x",
+        "speedup",
+        graphemes.as_secs_f64() / ascii.as_secs_f64()
+    );
+}
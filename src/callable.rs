@@ -0,0 +1,134 @@
+use std::fmt::Debug;
+use std::ops::RangeInclusive;
+use std::sync::Arc;
+
+use crate::value::Value;
+
+/// Why a call returned no value
+#[derive(Debug)]
+pub enum CallError {
+    /// A runtime error; the interpreter points it at the call's parenthesis
+    Error(String),
+    /// The program asked to stop with this exit status
+    Exit(i32),
+}
+
+/// Anything Lox code can call with `(...)`
+pub trait Callable: Debug + Send + Sync {
+    fn name(&self) -> &str;
+
+    /// How many arguments a call may pass, up to `usize::MAX` for any number
+    fn arity(&self) -> RangeInclusive<usize>;
+
+    fn call(&self, arguments: Vec<Value>) -> Result<Value, CallError>;
+
+    /// What `help()` prints for this callable
+    fn help(&self) -> String;
+}
+
+/// Fails with the message every call site reports when `function` can't take `len`
+/// arguments
+pub fn check_arity(function: &dyn Callable, len: usize) -> Result<(), String> {
+    let arity = function.arity();
+    if arity.contains(&len) {
+        return Ok(());
+    }
+    let expected = if arity.start() == arity.end() {
+        arity.start().to_string()
+    } else if *arity.end() == usize::MAX {
+        format!("at least {}", arity.start())
+    } else {
+        format!("{} to {}", arity.start(), arity.end())
+    };
+    Err(format!(
+        "{}() expects {} argument{}, got {}",
+        function.name(),
+        expected,
+        if *arity.start() == 1 && (*arity.end() == 1 || *arity.end() == usize::MAX) {
+            ""
+        } else {
+            "s"
+        },
+        len
+    ))
+}
+
+type NativeFn = dyn Fn(Vec<Value>) -> Result<Value, String> + Send + Sync;
+
+/// A function implemented in Rust, which may capture state of its own.
+///
+/// Errors are plain messages, which the interpreter reports as runtime errors.
+pub struct NativeFunction {
+    name: &'static str,
+    params: &'static [&'static str],
+    doc: &'static str,
+    function: Arc<NativeFn>,
+}
+
+impl NativeFunction {
+    /// `params` name the arguments for `help()`, and their count is the arity.
+    /// Names ending in `?` are optional, filled in with `nil` when not passed, and
+    /// a last name ending in `...` takes any number of arguments, so these come last.
+    pub fn new<F>(
+        name: &'static str,
+        params: &'static [&'static str],
+        doc: &'static str,
+        function: F,
+    ) -> NativeFunction
+    where
+        F: Fn(Vec<Value>) -> Result<Value, String> + Send + Sync + 'static,
+    {
+        NativeFunction {
+            name,
+            params,
+            doc,
+            function: Arc::new(function),
+        }
+    }
+
+    /// How the function is called, like `type(value)`
+    pub fn signature(&self) -> String {
+        format!("{}({})", self.name, self.params.join(", "))
+    }
+
+    fn is_variadic(&self) -> bool {
+        self.params.last().is_some_and(|last| last.ends_with("..."))
+    }
+}
+
+impl Callable for NativeFunction {
+    fn name(&self) -> &str {
+        self.name
+    }
+
+    fn arity(&self) -> RangeInclusive<usize> {
+        let required = self
+            .params
+            .iter()
+            .filter(|param| !param.ends_with('?') && !param.ends_with("..."))
+            .count();
+        if self.is_variadic() {
+            required..=usize::MAX
+        } else {
+            required..=self.params.len()
+        }
+    }
+
+    fn call(&self, mut arguments: Vec<Value>) -> Result<Value, CallError> {
+        let fixed = self.params.len() - self.is_variadic() as usize;
+        if arguments.len() < fixed {
+            arguments.resize(fixed, Value::Nil);
+        }
+        (self.function)(arguments).map_err(CallError::Error)
+    }
+
+    fn help(&self) -> String {
+        format!("{}\n    {}", self.signature(), self.doc)
+    }
+}
+
+impl Debug for NativeFunction {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "<native fn {}>", self.name)
+    }
+}
@@ -0,0 +1,144 @@
+use std::path::{Path, PathBuf};
+
+use rlox::interpreter::Options;
+use serde::{Deserialize, Serialize};
+
+/// Name of the per-directory config file
+pub const FILE_NAME: &str = ".rlox.toml";
+
+/// Settings that can come from `.rlox.toml` or the command line.
+///
+/// Every field is optional so a file only needs to mention what it changes;
+/// `resolve` fills in the defaults once all sources are merged.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Config {
+    pub check_arith: Option<bool>,
+    pub coerce_equality: Option<bool>,
+    pub disable_assertions: Option<bool>,
+    pub strict_concat: Option<bool>,
+    pub strict_math: Option<bool>,
+    pub stats: Option<bool>,
+    pub ast: Option<bool>,
+    pub repl: Repl,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Repl {
+    pub prompt: Option<String>,
+    pub continuation_prompt: Option<String>,
+    pub banner: Option<String>,
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(PathBuf, std::io::Error),
+    Toml(PathBuf, toml::de::Error),
+}
+
+impl std::fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ConfigError::Io(path, error) => write!(f, "{}: {}", path.display(), error),
+            ConfigError::Toml(path, error) => write!(f, "{}: {}", path.display(), error),
+        }
+    }
+}
+
+impl Config {
+    /// Loads the nearest `.rlox.toml` in `dir` or its ancestors, if there is one.
+    pub fn discover(dir: &Path) -> Result<Option<(PathBuf, Config)>, ConfigError> {
+        for dir in dir.ancestors() {
+            let path = dir.join(FILE_NAME);
+            if path.is_file() {
+                let config = Config::load(&path)?;
+                return Ok(Some((path, config)));
+            }
+        }
+        Ok(None)
+    }
+
+    pub fn load(path: &Path) -> Result<Config, ConfigError> {
+        let content =
+            std::fs::read_to_string(path).map_err(|error| ConfigError::Io(path.into(), error))?;
+        toml::from_str(&content).map_err(|error| ConfigError::Toml(path.into(), error))
+    }
+
+    /// Layers `overrides` on top of `self`, keeping values `overrides` leaves unset.
+    pub fn merge(self, overrides: Config) -> Config {
+        Config {
+            check_arith: overrides.check_arith.or(self.check_arith),
+            coerce_equality: overrides.coerce_equality.or(self.coerce_equality),
+            disable_assertions: overrides.disable_assertions.or(self.disable_assertions),
+            strict_concat: overrides.strict_concat.or(self.strict_concat),
+            strict_math: overrides.strict_math.or(self.strict_math),
+            stats: overrides.stats.or(self.stats),
+            ast: overrides.ast.or(self.ast),
+            repl: Repl {
+                prompt: overrides.repl.prompt.or(self.repl.prompt),
+                continuation_prompt: overrides
+                    .repl
+                    .continuation_prompt
+                    .or(self.repl.continuation_prompt),
+                banner: overrides.repl.banner.or(self.repl.banner),
+            },
+        }
+    }
+
+    /// Fills in the default of every setting still unset.
+    pub fn resolve(self) -> Settings {
+        Settings {
+            check_arith: self.check_arith.unwrap_or(false),
+            coerce_equality: self.coerce_equality.unwrap_or(false),
+            disable_assertions: self.disable_assertions.unwrap_or(false),
+            strict_concat: self.strict_concat.unwrap_or(false),
+            strict_math: self.strict_math.unwrap_or(false),
+            stats: self.stats.unwrap_or(false),
+            ast: self.ast.unwrap_or(false),
+            repl: ReplSettings {
+                prompt: self.repl.prompt.unwrap_or_else(|| String::from("> ")),
+                continuation_prompt: self
+                    .repl
+                    .continuation_prompt
+                    .unwrap_or_else(|| String::from("... ")),
+                banner: self.repl.banner,
+            },
+        }
+    }
+}
+
+/// The effective configuration, after merging every source and applying defaults
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Settings {
+    pub check_arith: bool,
+    pub coerce_equality: bool,
+    pub disable_assertions: bool,
+    pub strict_concat: bool,
+    pub strict_math: bool,
+    pub stats: bool,
+    pub ast: bool,
+    pub repl: ReplSettings,
+}
+
+impl Settings {
+    pub fn options(&self) -> Options {
+        Options {
+            check_arith: self.check_arith,
+            coerce_equality: self.coerce_equality,
+            disable_assertions: self.disable_assertions,
+            strict_concat: self.strict_concat,
+            strict_math: self.strict_math,
+            ..Options::default()
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct ReplSettings {
+    pub prompt: String,
+    pub continuation_prompt: String,
+    pub banner: Option<String>,
+}
@@ -0,0 +1,51 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::callable::{Callable, NativeFunction};
+use crate::interpreter::{Deadline, Options, Output};
+use crate::natives::{constants, natives};
+use crate::symbol::Symbol;
+use crate::value::Value;
+
+/// Names bound to values
+#[derive(Debug, Default)]
+pub struct Environment {
+    values: HashMap<Symbol, Value>,
+}
+
+impl Environment {
+    /// The global environment every program starts with, holding the builtins and `args`.
+    ///
+    /// Builtins that print, like `help()`, write to `stdout`, and `sleep()` wakes up by
+    /// `deadline`.
+    pub fn with_natives(options: &Options, stdout: &Output, deadline: &Deadline) -> Environment {
+        let mut environment = Environment::default();
+        for (name, value) in constants() {
+            environment.define(name, value);
+        }
+        let args = options
+            .args
+            .iter()
+            .map(|arg| Value::String(arg.as_str().into()))
+            .collect();
+        environment.define("args", Value::List(Arc::new(args)));
+        for native in natives(options, stdout, deadline) {
+            let name = Symbol::from(native.name());
+            environment.values.insert(name, Value::Function(native));
+        }
+        environment
+    }
+
+    pub fn define(&mut self, name: impl Into<Symbol>, value: Value) {
+        self.values.insert(name.into(), value);
+    }
+
+    pub fn define_native(&mut self, native: NativeFunction) {
+        let name = Symbol::from(native.name());
+        self.values.insert(name, Value::Function(Arc::new(native)));
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Value> {
+        self.values.get(name)
+    }
+}
@@ -1,4 +1,5 @@
-use crate::lexing::{LiteralValue, Token};
+use crate::lexing::Token;
+use crate::value::Value;
 
 #[derive(Debug)]
 pub enum Expr {
@@ -7,45 +8,187 @@ pub enum Expr {
         op: Token,
         right: Box<Expr>,
     },
+    Call {
+        callee: Box<Expr>,
+        paren: Token,
+        arguments: Vec<Expr>,
+    },
+    Conditional {
+        condition: Box<Expr>,
+        then_branch: Box<Expr>,
+        else_branch: Box<Expr>,
+    },
     Grouping {
         expr: Box<Expr>,
     },
+    Index {
+        object: Box<Expr>,
+        bracket: Token,
+        index: Box<Expr>,
+    },
+    List {
+        elements: Vec<Expr>,
+    },
     Literal {
-        value: Option<LiteralValue>,
+        value: Value,
+    },
+    Logical {
+        left: Box<Expr>,
+        op: Token,
+        right: Box<Expr>,
+    },
+    Map {
+        brace: Token,
+        entries: Vec<(Expr, Expr)>,
     },
     Unary {
         op: Token,
         right: Box<Expr>,
     },
+    Variable {
+        name: Token,
+    },
+}
+
+impl Expr {
+    /// Moves this expression out, leaving a `nil` literal in its place
+    pub(crate) fn take(&mut self) -> Expr {
+        std::mem::replace(self, Expr::Literal { value: Value::Nil })
+    }
+
+    /// Moves the children that have children of their own into `into`
+    fn take_subtrees(&mut self, into: &mut Vec<Expr>) {
+        let mut push = |expr: &mut Expr| {
+            if !matches!(expr, Expr::Literal { .. } | Expr::Variable { .. }) {
+                into.push(expr.take());
+            }
+        };
+        match self {
+            Expr::Binary { left, right, .. } | Expr::Logical { left, right, .. } => {
+                push(left);
+                push(right);
+            }
+            Expr::Call {
+                callee, arguments, ..
+            } => {
+                push(callee);
+                arguments.iter_mut().for_each(push);
+            }
+            Expr::Conditional {
+                condition,
+                then_branch,
+                else_branch,
+            } => {
+                push(condition);
+                push(then_branch);
+                push(else_branch);
+            }
+            Expr::Grouping { expr } => push(expr),
+            Expr::Index { object, index, .. } => {
+                push(object);
+                push(index);
+            }
+            Expr::List { elements } => elements.iter_mut().for_each(push),
+            Expr::Literal { .. } => (),
+            Expr::Map { entries, .. } => {
+                for (key, value) in entries.iter_mut() {
+                    push(key);
+                    push(value);
+                }
+            }
+            Expr::Unary { right, .. } => push(right),
+            Expr::Variable { .. } => (),
+        }
+    }
 }
 
+/// Dismantles the tree with an explicit stack, so dropping a deeply nested
+/// expression (like a long chain of `+`) can't overflow the host stack.
+impl Drop for Expr {
+    fn drop(&mut self) {
+        let mut pending = vec![];
+        self.take_subtrees(&mut pending);
+        while let Some(mut expr) = pending.pop() {
+            expr.take_subtrees(&mut pending);
+        }
+    }
+}
+
+/// Prints the tree as nested S-expressions with an explicit stack, like `Drop`, so
+/// printing a deeply nested expression can't overflow the host stack either.
 impl std::fmt::Display for Expr {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::result::Result<(), std::fmt::Error> {
-        let string = match self {
-            Expr::Binary { left, op, right } => parenthesize(&op.lexeme, &[left, right]),
-            Expr::Grouping { expr } => parenthesize(&"group", &[expr]),
-            Expr::Literal { value: None } => String::from("nil"),
-            Expr::Literal {
-                value: Some(LiteralValue::Bool(b)),
-            } => b.to_string(),
-            Expr::Literal {
-                value: Some(LiteralValue::Number(n)),
-            } => n.to_string(),
-            Expr::Literal {
-                value: Some(LiteralValue::String(s)),
-            } => s.clone(),
-            Expr::Unary { op, right } => parenthesize(&op.lexeme, &[right.as_ref()]),
-        };
-        write!(f, "{}", string)
+        let mut pending = vec![Piece::Expr(self)];
+        while let Some(piece) = pending.pop() {
+            let expr = match piece {
+                Piece::Text(text) => {
+                    f.write_str(text)?;
+                    continue;
+                }
+                Piece::Expr(expr) => expr,
+            };
+            let (name, children): (&str, Vec<&Expr>) = match expr {
+                Expr::Binary { left, op, right } | Expr::Logical { left, op, right } => {
+                    (&op.lexeme, vec![left, right])
+                }
+                Expr::Call {
+                    callee, arguments, ..
+                } => (
+                    "call",
+                    std::iter::once(callee.as_ref()).chain(arguments).collect(),
+                ),
+                Expr::Conditional {
+                    condition,
+                    then_branch,
+                    else_branch,
+                } => ("?:", vec![condition, then_branch, else_branch]),
+                Expr::Grouping { expr } => ("group", vec![expr]),
+                Expr::Index { object, index, .. } => ("index", vec![object, index]),
+                Expr::List { elements } => ("list", elements.iter().collect()),
+                Expr::Literal { value } => {
+                    write_literal(value, f)?;
+                    continue;
+                }
+                Expr::Map { entries, .. } => (
+                    "map",
+                    entries
+                        .iter()
+                        .flat_map(|(key, value)| vec![key, value])
+                        .collect(),
+                ),
+                Expr::Unary { op, right } => (&op.lexeme, vec![right]),
+                Expr::Variable { name } => {
+                    f.write_str(&name.lexeme)?;
+                    continue;
+                }
+            };
+            write!(f, "({}", name)?;
+            pending.push(Piece::Text(")"));
+            for child in children.into_iter().rev() {
+                pending.push(Piece::Expr(child));
+                pending.push(Piece::Text(" "));
+            }
+        }
+        Ok(())
     }
 }
 
-fn parenthesize(name: &str, exprs: &[&Expr]) -> String {
-    let mut buf = vec![String::from("("), String::from(name)];
-    for expr in exprs.iter() {
-        buf.push(String::from(" "));
-        buf.push(expr.to_string());
+/// What `Display for Expr` has left to write
+enum Piece<'a> {
+    Expr(&'a Expr),
+    Text(&'a str),
+}
+
+fn write_literal(value: &Value, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+    match value {
+        Value::Nil => write!(f, "nil"),
+        Value::Bool(b) => write!(f, "{}", b),
+        Value::Int(n) => write!(f, "{}", n),
+        Value::List(_) => write!(f, "<list>"),
+        Value::Map(_) => write!(f, "<map>"),
+        Value::Function(function) => write!(f, "{:?}", function),
+        Value::Number(n) => write!(f, "{}", n),
+        Value::Range(_) => write!(f, "<range>"),
+        Value::String(s) => write!(f, "{}", s),
     }
-    buf.push(String::from(")"));
-    buf.concat()
 }
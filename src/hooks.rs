@@ -0,0 +1,20 @@
+use crate::callable::Callable;
+use crate::lexing::Loc;
+use crate::stmt::Stmt;
+use crate::value::Value;
+
+/// Callbacks the interpreter makes as it runs, so tracers, profilers and debuggers can
+/// follow a program without patching the interpreter. Every method does nothing unless
+/// overridden.
+pub trait Hooks {
+    /// Before each top-level statement runs
+    fn on_statement(&mut self, _stmt: &Stmt) {}
+
+    /// Before `function` is called, once the arguments are evaluated and their number
+    /// checked; `loc` is the call's closing parenthesis
+    fn on_call(&mut self, _function: &dyn Callable, _arguments: &[Value], _loc: &Loc) {}
+
+    /// After `function` returns, with its result, or `None` if it raised an error or
+    /// called `exit()`
+    fn on_return(&mut self, _function: &dyn Callable, _result: Option<&Value>) {}
+}
@@ -1,6 +1,20 @@
+use std::collections::{BTreeMap, HashSet};
+use std::convert::TryFrom;
+use std::io::{self, Read, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::time::{Duration, Instant};
+
+use crate::callable::{check_arity, CallError};
+use crate::environment::Environment;
 use crate::expr::Expr;
-use crate::lexing::{LiteralValue, Loc, Token, TokenKind};
+use crate::hooks::Hooks;
+use crate::lexing::{Loc, Token, TokenKind};
+use crate::program::Program;
+use crate::snapshot::{self, SnapshotError};
 use crate::stmt::Stmt;
+use crate::symbol::Symbol;
+use crate::value::{approx_size, coercing_eq, lox_eq, MapKey, Range, Value};
 
 #[derive(Debug)]
 pub struct RuntimeError {
@@ -8,152 +22,1138 @@ pub struct RuntimeError {
     loc: Loc,
 }
 
-pub fn interpret(statements: Vec<Stmt>) -> Result<(), RuntimeError> {
-    for stmt in statements {
-        execute(stmt)?
+impl std::fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "[{}] Error: {}", self.loc, self.message)
     }
-    Ok(())
 }
 
-fn execute(stmt: Stmt) -> Result<(), RuntimeError> {
-    match stmt {
-        Stmt::Expr(expr) => evaluate(expr)?,
-        Stmt::Print(expr) => {
-            let value = evaluate(expr)?;
-            println!("{}", stringify(value));
-            None
+impl std::error::Error for RuntimeError {}
+
+/// Why a program stopped before running to the end
+#[derive(Debug)]
+pub enum Halt {
+    Error(RuntimeError),
+    /// `exit()` was called with this status
+    Exit(i32),
+    /// The program ran into one of the limits set in `Options`
+    Limit(Limit),
+}
+
+/// A limit from `Options` that a program exceeded
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Limit {
+    /// `max_steps`, with its value
+    Steps(u64),
+    /// `timeout`, with its value
+    Time(Duration),
+    /// `max_memory`, with its value
+    Memory(usize),
+}
+
+impl std::fmt::Display for Limit {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Limit::Steps(steps) => write!(f, "Stopped after the limit of {} steps", steps),
+            Limit::Time(timeout) => write!(f, "Stopped after the time limit of {:?}", timeout),
+            Limit::Memory(bytes) => write!(f, "Stopped at the memory limit of {} bytes", bytes),
         }
-    };
-    Ok(())
+    }
 }
 
-fn evaluate(expr: Expr) -> Result<Option<LiteralValue>, RuntimeError> {
-    match expr {
-        Expr::Binary { left, op, right } => {
-            let left = evaluate(*left)?;
-            let right = evaluate(*right)?;
-            match op.kind {
-                TokenKind::BangEqual => Ok(Some(LiteralValue::Bool(!is_equal(left, right)))),
-                TokenKind::EqualEqual => Ok(Some(LiteralValue::Bool(is_equal(left, right)))),
-                TokenKind::Greater => {
-                    let (lhs, rhs) = expect_numbers(left, op, right)?;
-                    Ok(Some(LiteralValue::Bool(lhs > rhs)))
-                }
-                TokenKind::GreaterEqual => {
-                    let (lhs, rhs) = expect_numbers(left, op, right)?;
-                    Ok(Some(LiteralValue::Bool(lhs >= rhs)))
-                }
-                TokenKind::Less => {
-                    let (lhs, rhs) = expect_numbers(left, op, right)?;
-                    Ok(Some(LiteralValue::Bool(lhs < rhs)))
-                }
-                TokenKind::LessEqual => {
-                    let (lhs, rhs) = expect_numbers(left, op, right)?;
-                    Ok(Some(LiteralValue::Bool(lhs <= rhs)))
-                }
-                TokenKind::Minus => {
-                    let (lhs, rhs) = expect_numbers(left, op, right)?;
-                    Ok(Some(LiteralValue::Number(lhs - rhs)))
-                }
-                TokenKind::Plus => match (left, right) {
-                    (Some(LiteralValue::Number(lhs)), Some(LiteralValue::Number(rhs))) => {
-                        Ok(Some(LiteralValue::Number(lhs + rhs)))
+impl std::fmt::Display for Halt {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Halt::Error(error) => write!(f, "{}", error),
+            Halt::Exit(code) => write!(f, "Exited with status {}", code),
+            Halt::Limit(limit) => write!(f, "{}", limit),
+        }
+    }
+}
+
+impl std::error::Error for Halt {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Halt::Error(error) => Some(error),
+            Halt::Exit(_) | Halt::Limit(_) => None,
+        }
+    }
+}
+
+impl From<RuntimeError> for Halt {
+    fn from(error: RuntimeError) -> Halt {
+        Halt::Error(error)
+    }
+}
+
+/// Where an interpreter and the builtins that print, like `help()`, send their text.
+///
+/// Clones share the writer, so swapping it redirects every holder at once.
+#[derive(Clone)]
+pub struct Output(Arc<Mutex<Box<dyn Write + Send>>>);
+
+impl Output {
+    pub fn new(out: impl Write + Send + 'static) -> Output {
+        Output(Arc::new(Mutex::new(Box::new(out))))
+    }
+
+    /// The process's standard output
+    pub fn stdout() -> Output {
+        Output::new(io::stdout())
+    }
+
+    fn replace(&self, out: impl Write + Send + 'static) {
+        *self.lock() = Box::new(out);
+    }
+
+    /// A holder that panicked mid-write leaves nothing to repair, so poisoning is ignored
+    fn lock(&self) -> MutexGuard<'_, Box<dyn Write + Send>> {
+        self.0.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl Write for &Output {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.lock().write(buf)
+    }
+
+    fn write_all(&mut self, buf: &[u8]) -> io::Result<()> {
+        self.lock().write_all(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.lock().flush()
+    }
+}
+
+/// When the current run must stop, shared with builtins that wait, like `sleep()`, so
+/// they wake up in time. Set by each run that has a `timeout`.
+#[derive(Clone, Debug, Default)]
+pub struct Deadline(Arc<Mutex<Option<Instant>>>);
+
+impl Deadline {
+    pub fn get(&self) -> Option<Instant> {
+        *self.0.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn set(&self, deadline: Option<Instant>) {
+        *self.0.lock().unwrap_or_else(PoisonError::into_inner) = deadline;
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Options {
+    /// Raise errors for NaN, overflow, underflow and near-equal float comparisons
+    pub check_arith: bool,
+    /// Skip `assert` statements without evaluating them
+    pub disable_assertions: bool,
+    /// Make `+` reject a string and a number instead of concatenating them
+    pub strict_concat: bool,
+    /// Make `/` and `%` raise an error for a zero divisor instead of returning infinity or NaN
+    pub strict_math: bool,
+    /// Make `==` and `!=` treat a number and a string holding the same number as equal
+    pub coerce_equality: bool,
+    /// Setting this flag from another thread stops evaluation with a runtime error
+    pub interrupt: Option<Arc<AtomicBool>>,
+    /// How many steps each run may take, roughly one per statement, operator and operand,
+    /// before it stops with `Halt::Limit`
+    pub max_steps: Option<u64>,
+    /// How long each run may take before it stops with `Halt::Limit`. Checked between
+    /// steps and after calls; `sleep()` wakes up when it runs out.
+    pub timeout: Option<Duration>,
+    /// Roughly how many bytes the values in global variables, plus the value being
+    /// computed, may take before the program stops with `Halt::Limit`. A value is
+    /// measured once it exists, so one that overshoots is still built before the check.
+    pub max_memory: Option<usize>,
+    /// Let `httpGet()` make requests, with the `net` feature
+    pub allow_net: bool,
+    /// The command-line arguments given to the script, bound to the global `args`
+    pub args: Vec<String>,
+}
+
+/// Runs `statements` with a fresh global environment, see `Interpreter::interpret`
+pub fn interpret(statements: &[Stmt], options: &Options) -> Result<Option<Value>, Halt> {
+    Interpreter::new(options.clone()).interpret(statements)
+}
+
+/// Runs programs against a global environment that outlives each of them, so what
+/// one defines is visible to the next, like lines typed into the REPL
+pub struct Interpreter {
+    globals: Environment,
+    /// Globals defined by the program rather than builtin, which `save` writes
+    defined: HashSet<Symbol>,
+    options: Options,
+    stdout: Output,
+    stderr: Box<dyn Write + Send>,
+    hooks: Option<Box<dyn Hooks + Send>>,
+    /// Steps taken by the current run
+    steps: u64,
+    /// When the current run must stop, with a `timeout`
+    deadline: Deadline,
+    /// Approximate bytes held by global variables, tracked with a `max_memory`
+    memory: usize,
+}
+
+impl Interpreter {
+    pub fn new(options: Options) -> Interpreter {
+        let stdout = Output::stdout();
+        let deadline = Deadline::default();
+        Interpreter {
+            globals: Environment::with_natives(&options, &stdout, &deadline),
+            defined: HashSet::new(),
+            options,
+            stdout,
+            stderr: Box::new(io::stderr()),
+            hooks: None,
+            steps: 0,
+            deadline,
+            memory: 0,
+        }
+    }
+
+    /// Sends what `print` writes to `stdout` instead of the process's standard output
+    pub fn with_stdout(self, stdout: impl Write + Send + 'static) -> Interpreter {
+        self.stdout.replace(stdout);
+        self
+    }
+
+    /// Sends what `eprint` writes to `stderr` instead of the process's standard error
+    pub fn with_stderr(self, stderr: impl Write + Send + 'static) -> Interpreter {
+        Interpreter {
+            stderr: Box::new(stderr),
+            ..self
+        }
+    }
+
+    /// Reports statements and calls to `hooks` as they run
+    pub fn with_hooks(self, hooks: impl Hooks + Send + 'static) -> Interpreter {
+        Interpreter {
+            hooks: Some(Box::new(hooks)),
+            ..self
+        }
+    }
+
+    /// Writes the globals the program defined, in a format `restore` reads back.
+    ///
+    /// Builtin functions stored in variables are saved by name.
+    pub fn save(&self, mut out: impl Write) -> io::Result<()> {
+        let mut names: Vec<&str> = self.defined.iter().map(|name| &**name).collect();
+        names.sort_unstable();
+        let globals = names
+            .into_iter()
+            .filter_map(|name| Some((name, self.globals.get(name)?)));
+        snapshot::write(globals, &mut out)
+    }
+
+    /// Defines the globals a `save` wrote, replacing ones of the same name.
+    ///
+    /// Saved builtins are looked up among the current globals, so they are the very
+    /// functions already defined here, not copies. Nothing is defined if the snapshot is
+    /// invalid; going over `max_memory` stops partway through.
+    pub fn restore(&mut self, mut input: impl Read) -> Result<(), SnapshotError> {
+        let live = &self.globals;
+        let globals = snapshot::read(&mut input, |name| match live.get(name) {
+            Some(Value::Function(function)) if function.name() == name => {
+                Some(Arc::clone(function))
+            }
+            _ => None,
+        })?;
+        for (name, value) in globals {
+            self.define(Symbol::from(name), value)
+                .map_err(SnapshotError::Limit)?;
+        }
+        Ok(())
+    }
+
+    /// Stops at the first error, keeping whatever the statements before it defined.
+    ///
+    /// Returns the value of the last statement when it is an expression, like `1 + 2;`.
+    pub fn interpret(&mut self, statements: &[Stmt]) -> Result<Option<Value>, Halt> {
+        self.steps = 0;
+        self.deadline.set(
+            self.options
+                .timeout
+                .and_then(|timeout| Instant::now().checked_add(timeout)),
+        );
+        let mut last = None;
+        for stmt in statements {
+            self.step()?;
+            self.check_deadline()?;
+            if let Some(hooks) = &mut self.hooks {
+                hooks.on_statement(stmt);
+            }
+            last = self.execute_stmt(stmt)?;
+        }
+        Ok(last)
+    }
+
+    /// Runs a compiled `program`, like `interpret` does its statements
+    pub fn execute(&mut self, program: &Program) -> Result<Option<Value>, Halt> {
+        self.interpret(program.statements())
+    }
+
+    /// Returns the value of an expression statement
+    fn execute_stmt(&mut self, stmt: &Stmt) -> Result<Option<Value>, Halt> {
+        match stmt {
+            Stmt::Assert { .. } if self.options.disable_assertions => (),
+            Stmt::Assert {
+                keyword,
+                condition,
+                message,
+            } => {
+                if !is_truthy(self.evaluate(condition)?) {
+                    let message = match message {
+                        Some(message) => {
+                            format!("Assertion failed: {}", stringify(self.evaluate(message)?))
+                        }
+                        None => String::from("Assertion failed"),
+                    };
+                    return Err(Halt::Error(RuntimeError {
+                        message,
+                        loc: keyword.loc.clone(),
+                    }));
+                }
+            }
+            Stmt::Expr(expr) => return Ok(Some(self.evaluate(expr)?)),
+            Stmt::Eprint { keyword, expr } => {
+                let text = stringify(self.evaluate(expr)?);
+                writeln!(self.stderr, "{}", text).map_err(|error| output_error(keyword, error))?;
+            }
+            Stmt::Print { keyword, expr } => {
+                let text = stringify(self.evaluate(expr)?);
+                writeln!(&self.stdout, "{}", text).map_err(|error| output_error(keyword, error))?;
+            }
+            Stmt::Var { name, initializer } => {
+                let value = match initializer {
+                    Some(initializer) => self.evaluate(initializer)?,
+                    None => Value::Nil,
+                };
+                self.define(name.lexeme.clone(), value)
+                    .map_err(Halt::Limit)?;
+            }
+        }
+        Ok(None)
+    }
+
+    /// Defines a global the program made, accounting for it against `max_memory`
+    fn define(&mut self, name: Symbol, value: Value) -> Result<(), Limit> {
+        if let Some(max_memory) = self.options.max_memory {
+            let replaced = self.globals.get(&name).map_or(0, approx_size);
+            let memory = self.memory.saturating_sub(replaced) + approx_size(&value);
+            if memory > max_memory {
+                return Err(Limit::Memory(max_memory));
+            }
+            self.memory = memory;
+        }
+        self.defined.insert(name.clone());
+        self.globals.define(name, value);
+        Ok(())
+    }
+
+    /// Counts a step against `max_steps`, and every so often checks the `timeout`
+    fn step(&mut self) -> Result<(), Halt> {
+        self.steps += 1;
+        if let Some(max_steps) = self.options.max_steps {
+            if self.steps > max_steps {
+                return Err(Halt::Limit(Limit::Steps(max_steps)));
+            }
+        }
+        if self.steps.is_multiple_of(1024) {
+            self.check_deadline()?;
+        }
+        Ok(())
+    }
+
+    /// Stops the run if `value`, on top of what globals hold, goes over `max_memory`
+    fn check_memory(&self, value: &Value) -> Result<(), Halt> {
+        if let Some(max_memory) = self.options.max_memory {
+            if self.memory + approx_size(value) > max_memory {
+                return Err(Halt::Limit(Limit::Memory(max_memory)));
+            }
+        }
+        Ok(())
+    }
+
+    fn check_deadline(&self) -> Result<(), Halt> {
+        if let (Some(timeout), Some(deadline)) = (self.options.timeout, self.deadline.get()) {
+            if Instant::now() >= deadline {
+                return Err(Halt::Limit(Limit::Time(timeout)));
+            }
+        }
+        Ok(())
+    }
+
+    fn evaluate(&mut self, expr: &Expr) -> Result<Value, Halt> {
+        let mut tasks = vec![Task::Evaluate(expr)];
+        let mut values: Vec<Value> = vec![];
+        while let Some(task) = tasks.pop() {
+            self.step()?;
+            if let (Some(interrupt), Some(token)) = (&self.options.interrupt, task.token()) {
+                if interrupt.load(Ordering::Relaxed) {
+                    return Err(Halt::Error(RuntimeError {
+                        message: String::from("Interrupted"),
+                        loc: token.loc.clone(),
+                    }));
+                }
+            }
+            match task {
+                Task::Evaluate(Expr::Binary { left, op, right }) => {
+                    tasks.push(Task::Binary(op));
+                    tasks.push(Task::Evaluate(right));
+                    tasks.push(Task::Evaluate(left));
+                }
+                Task::Evaluate(Expr::Call {
+                    callee,
+                    paren,
+                    arguments,
+                }) => {
+                    tasks.push(Task::Call(paren, arguments.len()));
+                    for argument in arguments.iter().rev() {
+                        tasks.push(Task::Evaluate(argument));
+                    }
+                    tasks.push(Task::Evaluate(callee));
+                }
+                Task::Evaluate(Expr::Conditional {
+                    condition,
+                    then_branch,
+                    else_branch,
+                }) => {
+                    tasks.push(Task::Conditional {
+                        then_branch,
+                        else_branch,
+                    });
+                    tasks.push(Task::Evaluate(condition));
+                }
+                Task::Evaluate(Expr::Grouping { expr }) => tasks.push(Task::Evaluate(expr)),
+                Task::Evaluate(Expr::Index {
+                    object,
+                    bracket,
+                    index,
+                }) => {
+                    tasks.push(Task::Index(bracket));
+                    tasks.push(Task::Evaluate(index));
+                    tasks.push(Task::Evaluate(object));
+                }
+                Task::Evaluate(Expr::List { elements }) => {
+                    tasks.push(Task::List(elements.len()));
+                    for element in elements.iter().rev() {
+                        tasks.push(Task::Evaluate(element));
+                    }
+                }
+                Task::Evaluate(Expr::Literal { value }) => values.push(value.clone()),
+                Task::Evaluate(Expr::Logical { left, op, right }) => {
+                    tasks.push(Task::Logical { op, right });
+                    tasks.push(Task::Evaluate(left));
+                }
+                Task::Evaluate(Expr::Map { brace, entries }) => {
+                    tasks.push(Task::Map(brace, entries.len()));
+                    for (key, value) in entries.iter().rev() {
+                        tasks.push(Task::Evaluate(value));
+                        tasks.push(Task::Evaluate(key));
+                    }
+                }
+                Task::Evaluate(Expr::Unary { op, right }) => {
+                    tasks.push(Task::Unary(op));
+                    tasks.push(Task::Evaluate(right));
+                }
+                Task::Evaluate(Expr::Variable { name }) => {
+                    values.push(look_up(&self.globals, name)?)
+                }
+                Task::Binary(op) => {
+                    let right = pop_value(&mut values);
+                    let left = pop_value(&mut values);
+                    let operands = Operands::of(&left, &right);
+                    let result = binary(left, op, right, &self.options)?;
+                    if self.options.check_arith {
+                        check_arith(operands, op, &result)?;
+                    }
+                    self.check_memory(&result)?;
+                    values.push(result);
+                }
+                Task::Call(paren, len) => {
+                    let arguments = values.split_off(values.len() - len);
+                    let callee = pop_value(&mut values);
+                    let result = call(callee, paren, arguments, &mut self.hooks)?;
+                    // A call may take long, and `sleep()` returns early at the deadline
+                    self.check_deadline()?;
+                    self.check_memory(&result)?;
+                    values.push(result);
+                }
+                Task::Conditional {
+                    then_branch,
+                    else_branch,
+                } => {
+                    if is_truthy(pop_value(&mut values)) {
+                        tasks.push(Task::Evaluate(then_branch));
+                    } else {
+                        tasks.push(Task::Evaluate(else_branch));
+                    }
+                }
+                Task::Index(bracket) => {
+                    let index = pop_value(&mut values);
+                    let object = pop_value(&mut values);
+                    values.push(index_value(object, bracket, index)?);
+                }
+                Task::List(len) => {
+                    let list = Value::List(Arc::new(values.split_off(values.len() - len)));
+                    self.check_memory(&list)?;
+                    values.push(list);
+                }
+                Task::Logical { op, right } => {
+                    let left = pop_value(&mut values);
+                    if short_circuits(op, &left) {
+                        values.push(left);
+                    } else {
+                        tasks.push(Task::Evaluate(right));
                     }
-                    (Some(LiteralValue::String(lhs)), Some(LiteralValue::String(rhs))) => {
-                        Ok(Some(LiteralValue::String(lhs + &rhs)))
+                }
+                Task::Map(brace, len) => {
+                    let mut map = BTreeMap::new();
+                    let mut flat = values.split_off(values.len() - 2 * len).into_iter();
+                    while let (Some(key), Some(value)) = (flat.next(), flat.next()) {
+                        map.insert(expect_map_key(brace, key)?, value);
                     }
-                    (_, _) => Err(RuntimeError {
-                        message: format!(
-                            "Operator {} expects either two numeric or two string operands",
-                            op.lexeme
-                        ),
-                        loc: op.loc,
-                    }),
-                },
-                TokenKind::Slash => {
-                    let (lhs, rhs) = expect_numbers(left, op, right)?;
-                    Ok(Some(LiteralValue::Number(lhs / rhs)))
-                }
-                TokenKind::Star => {
-                    let (lhs, rhs) = expect_numbers(left, op, right)?;
-                    Ok(Some(LiteralValue::Number(lhs * rhs)))
-                }
-                _ => Err(RuntimeError {
-                    message: format!("Invalid binary operator {}", op.lexeme),
-                    loc: op.loc,
+                    let map = Value::Map(Arc::new(map));
+                    self.check_memory(&map)?;
+                    values.push(map);
+                }
+                Task::Unary(op) => {
+                    let right = pop_value(&mut values);
+                    values.push(unary(op, right)?);
+                }
+            }
+        }
+        Ok(pop_value(&mut values))
+    }
+}
+
+/// A pending unit of work for `evaluate`.
+///
+/// Expressions are evaluated with an explicit stack of tasks instead of
+/// recursing per node, so arbitrarily deep expression trees can't overflow
+/// the host stack. `Evaluate` pushes the value of an expression onto the
+/// value stack; the remaining variants pop the values of already evaluated
+/// operands and push their result.
+enum Task<'a> {
+    Evaluate(&'a Expr),
+    Binary(&'a Token),
+    Call(&'a Token, usize),
+    Conditional {
+        then_branch: &'a Expr,
+        else_branch: &'a Expr,
+    },
+    Index(&'a Token),
+    List(usize),
+    Logical {
+        op: &'a Token,
+        right: &'a Expr,
+    },
+    Map(&'a Token, usize),
+    Unary(&'a Token),
+}
+
+impl<'a> Task<'a> {
+    /// The operator a task applies, used to locate interruptions
+    fn token(&self) -> Option<&'a Token> {
+        match self {
+            Task::Binary(op) | Task::Index(op) | Task::Map(op, _) | Task::Unary(op) => Some(op),
+            Task::Call(paren, _) => Some(paren),
+            Task::Logical { op, .. } => Some(op),
+            Task::Conditional { .. } | Task::Evaluate(_) | Task::List(_) => None,
+        }
+    }
+}
+
+fn output_error(keyword: &Token, error: io::Error) -> RuntimeError {
+    RuntimeError {
+        message: format!("{} failed: {}", keyword.lexeme, error),
+        loc: keyword.loc.clone(),
+    }
+}
+
+fn pop_value(values: &mut Vec<Value>) -> Value {
+    match values.pop() {
+        Some(value) => value,
+        None => panic!("Value stack underflow while evaluating. This is a bug."),
+    }
+}
+
+fn binary(left: Value, op: &Token, right: Value, options: &Options) -> Result<Value, RuntimeError> {
+    match op.kind {
+        TokenKind::Ampersand => {
+            let (lhs, rhs) = expect_integers(left, op, right)?;
+            Ok(Value::Int(lhs & rhs))
+        }
+        TokenKind::BangEqual => Ok(Value::Bool(!equals(&left, &right, options))),
+        TokenKind::DotDot | TokenKind::DotDotEqual => {
+            let (start, end) = expect_range_bounds(left, op, right)?;
+            Ok(Value::Range(Range {
+                start,
+                end,
+                inclusive: matches!(op.kind, TokenKind::DotDotEqual),
+            }))
+        }
+        TokenKind::EqualEqual => Ok(Value::Bool(equals(&left, &right, options))),
+        TokenKind::Caret => {
+            let (lhs, rhs) = expect_integers(left, op, right)?;
+            Ok(Value::Int(lhs ^ rhs))
+        }
+        TokenKind::Greater => {
+            let (lhs, rhs) = expect_numbers(left, op, right)?;
+            Ok(Value::Bool(lhs > rhs))
+        }
+        TokenKind::GreaterEqual => {
+            let (lhs, rhs) = expect_numbers(left, op, right)?;
+            Ok(Value::Bool(lhs >= rhs))
+        }
+        TokenKind::GreaterGreater => {
+            let (lhs, rhs) = expect_integers(left, op, right)?;
+            let rhs = expect_shift_amount(op, rhs)?;
+            Ok(Value::Int(lhs >> rhs))
+        }
+        TokenKind::Less => {
+            let (lhs, rhs) = expect_numbers(left, op, right)?;
+            Ok(Value::Bool(lhs < rhs))
+        }
+        TokenKind::LessEqual => {
+            let (lhs, rhs) = expect_numbers(left, op, right)?;
+            Ok(Value::Bool(lhs <= rhs))
+        }
+        TokenKind::LessLess => {
+            let (lhs, rhs) = expect_integers(left, op, right)?;
+            let rhs = expect_shift_amount(op, rhs)?;
+            Ok(Value::Int(lhs << rhs))
+        }
+        TokenKind::Minus => arithmetic(left, op, right, i64::checked_sub, |lhs, rhs| lhs - rhs),
+        TokenKind::Percent => {
+            check_divisor(op, &right, options)?;
+            // `i64::MIN % -1` overflows computing the quotient, but the remainder is just 0
+            let rem = |lhs: i64, rhs: i64| (rhs != 0).then(|| lhs.wrapping_rem(rhs));
+            arithmetic(left, op, right, rem, |lhs, rhs| lhs % rhs)
+        }
+        TokenKind::Pipe => {
+            let (lhs, rhs) = expect_integers(left, op, right)?;
+            Ok(Value::Int(lhs | rhs))
+        }
+        TokenKind::Plus => match (&left, &right) {
+            (Value::String(lhs), Value::String(rhs)) => {
+                Ok(Value::String([&**lhs, &**rhs].concat().into()))
+            }
+            (Value::String(lhs), number)
+                if !options.strict_concat && as_number(number).is_some() =>
+            {
+                Ok(Value::String([&**lhs, &stringify(right)].concat().into()))
+            }
+            (number, Value::String(rhs))
+                if !options.strict_concat && as_number(number).is_some() =>
+            {
+                Ok(Value::String([&stringify(left), &**rhs].concat().into()))
+            }
+            (lhs, rhs) if as_number(lhs).is_some() && as_number(rhs).is_some() => {
+                arithmetic(left, op, right, i64::checked_add, |lhs, rhs| lhs + rhs)
+            }
+            (_, _) => Err(RuntimeError {
+                message: format!(
+                    "Operator {} expects two numbers, or a string and {}",
+                    op.lexeme,
+                    if options.strict_concat {
+                        "a string"
+                    } else {
+                        "a string or number"
+                    }
+                ),
+                loc: op.loc.clone(),
+            }),
+        },
+        TokenKind::Slash => {
+            check_divisor(op, &right, options)?;
+            let (lhs, rhs) = expect_numbers(left, op, right)?;
+            Ok(Value::Number(lhs / rhs))
+        }
+        TokenKind::Star => arithmetic(left, op, right, i64::checked_mul, |lhs, rhs| lhs * rhs),
+        TokenKind::StarStar => arithmetic(
+            left,
+            op,
+            right,
+            |lhs, rhs| u32::try_from(rhs).ok().and_then(|rhs| lhs.checked_pow(rhs)),
+            f64::powf,
+        ),
+        _ => Err(RuntimeError {
+            message: format!("Invalid binary operator {}", op.lexeme),
+            loc: op.loc.clone(),
+        }),
+    }
+}
+
+fn equals(left: &Value, right: &Value, options: &Options) -> bool {
+    if options.coerce_equality {
+        coercing_eq(left, right)
+    } else {
+        lox_eq(left, right)
+    }
+}
+
+/// Rejects a zero divisor under `strict_math`
+fn check_divisor(op: &Token, divisor: &Value, options: &Options) -> Result<(), RuntimeError> {
+    if options.strict_math && as_number(divisor) == Some(0.0) {
+        return Err(RuntimeError {
+            message: format!("Division by zero in operator {}", op.lexeme),
+            loc: op.loc.clone(),
+        });
+    }
+    Ok(())
+}
+
+/// Applies `int_op` when both operands are integers, falling back to `float_op` when
+/// either is a float or the integer operation has no exact result (overflow, `% 0`).
+fn arithmetic(
+    left: Value,
+    op: &Token,
+    right: Value,
+    int_op: fn(i64, i64) -> Option<i64>,
+    float_op: fn(f64, f64) -> f64,
+) -> Result<Value, RuntimeError> {
+    if let (Value::Int(lhs), Value::Int(rhs)) = (&left, &right) {
+        if let Some(result) = int_op(*lhs, *rhs) {
+            return Ok(Value::Int(result));
+        }
+    }
+    let (lhs, rhs) = expect_numbers(left, op, right)?;
+    Ok(Value::Number(float_op(lhs, rhs)))
+}
+
+fn look_up(globals: &Environment, name: &Token) -> Result<Value, RuntimeError> {
+    match globals.get(&name.lexeme) {
+        Some(value) => Ok(value.clone()),
+        None => Err(RuntimeError {
+            message: format!("Undefined variable '{}'", name.lexeme),
+            loc: name.loc.clone(),
+        }),
+    }
+}
+
+fn call(
+    callee: Value,
+    paren: &Token,
+    arguments: Vec<Value>,
+    hooks: &mut Option<Box<dyn Hooks + Send>>,
+) -> Result<Value, Halt> {
+    let function = match &callee {
+        Value::Function(function) => Arc::clone(function),
+        _ => {
+            return Err(Halt::Error(RuntimeError {
+                message: format!("Only functions can be called, got {}", stringify(callee)),
+                loc: paren.loc.clone(),
+            }))
+        }
+    };
+    if let Err(message) = check_arity(function.as_ref(), arguments.len()) {
+        return Err(Halt::Error(RuntimeError {
+            message,
+            loc: paren.loc.clone(),
+        }));
+    }
+    if let Some(hooks) = hooks {
+        hooks.on_call(function.as_ref(), &arguments, &paren.loc);
+    }
+    let result = function.call(arguments);
+    if let Some(hooks) = hooks {
+        hooks.on_return(function.as_ref(), result.as_ref().ok());
+    }
+    result.map_err(|error| match error {
+        CallError::Error(message) => Halt::Error(RuntimeError {
+            message,
+            loc: paren.loc.clone(),
+        }),
+        CallError::Exit(code) => Halt::Exit(code),
+    })
+}
+
+/// Whether the left operand of a logical operator is already its result.
+fn short_circuits(op: &Token, left: &Value) -> bool {
+    match op.kind {
+        TokenKind::QuestionQuestion => !matches!(left, Value::Nil),
+        _ => panic!("Unknown logical operator {:?}. This is a bug.", op.kind),
+    }
+}
+
+fn index_value(object: Value, bracket: &Token, index: Value) -> Result<Value, RuntimeError> {
+    match &object {
+        Value::List(elements) => {
+            let len = elements.len();
+            match index {
+                Value::Int(i) if 0 <= i && (i as usize) < len => Ok(elements[i as usize].clone()),
+                Value::Int(i) => Err(RuntimeError {
+                    message: format!("List index {} out of bounds for length {}", i, len),
+                    loc: bracket.loc.clone(),
+                }),
+                other => Err(RuntimeError {
+                    message: format!("List index must be an integer, got {}", stringify(other)),
+                    loc: bracket.loc.clone(),
                 }),
             }
         }
-        Expr::Grouping { expr } => evaluate(*expr),
-        Expr::Literal { value } => Ok(value),
-        Expr::Unary { op, right } => {
-            let right = evaluate(*right)?;
-            match op {
-                Token {
-                    kind: TokenKind::Minus,
-                    ..
-                } => {
-                    let rhs = expect_number(op, right)?;
-                    Ok(Some(LiteralValue::Number(-rhs)))
-                }
-                Token {
-                    kind: TokenKind::Bang,
-                    ..
-                } => Ok(Some(LiteralValue::Bool(!is_truthy(right)))),
-                tok => Err(RuntimeError {
-                    message: String::from("invalid unary operator?"),
-                    loc: tok.loc,
+        Value::Range(range) => match index {
+            Value::Int(i) => match range.get(i) {
+                Some(element) => Ok(Value::Int(element)),
+                None => Err(RuntimeError {
+                    message: format!("Range index {} out of bounds for length {}", i, range.len()),
+                    loc: bracket.loc.clone(),
                 }),
+            },
+            other => Err(RuntimeError {
+                message: format!("Range index must be an integer, got {}", stringify(other)),
+                loc: bracket.loc.clone(),
+            }),
+        },
+        // Missing keys read as nil
+        Value::Map(map) => {
+            let key = expect_map_key(bracket, index)?;
+            Ok(map.get(&key).cloned().unwrap_or_default())
+        }
+        _ => Err(RuntimeError {
+            message: format!(
+                "Only lists, maps and ranges can be indexed, got {}",
+                stringify(object)
+            ),
+            loc: bracket.loc.clone(),
+        }),
+    }
+}
+
+/// The value of `left op right` when it is the same under any `Options`, for constant
+/// folding. Evaluates with every check turned on, since those only ever add errors.
+pub(crate) fn fold_binary(left: &Value, op: &Token, right: &Value) -> Option<Value> {
+    let options = Options {
+        check_arith: true,
+        strict_concat: true,
+        strict_math: true,
+        ..Options::default()
+    };
+    let operands = Operands::of(left, right);
+    let result = binary(left.clone(), op, right.clone(), &options).ok()?;
+    check_arith(operands, op, &result).ok()?;
+    // `1 == "1"` is the one case where turning a check on changes the result
+    let coercing = Options {
+        coerce_equality: true,
+        ..options
+    };
+    let coerced = binary(left.clone(), op, right.clone(), &coercing).ok()?;
+    lox_eq(&result, &coerced).then_some(result)
+}
+
+/// The value of `op right`, unless it raises an error
+pub(crate) fn fold_unary(op: &Token, right: &Value) -> Option<Value> {
+    unary(op, right.clone()).ok()
+}
+
+/// Numeric operands of a binary operation, kept around for `check_arith`
+struct Operands {
+    lhs: f64,
+    rhs: f64,
+    both_ints: bool,
+}
+
+impl Operands {
+    fn of(lhs: &Value, rhs: &Value) -> Option<Operands> {
+        Some(Operands {
+            lhs: as_number(lhs)?,
+            rhs: as_number(rhs)?,
+            both_ints: matches!((lhs, rhs), (Value::Int(_), Value::Int(_))),
+        })
+    }
+}
+
+/// Turns silent floating-point anomalies into errors for `--check-arith`
+fn check_arith(operands: Option<Operands>, op: &Token, result: &Value) -> Result<(), RuntimeError> {
+    let Operands {
+        lhs,
+        rhs,
+        both_ints,
+    } = match operands {
+        Some(operands) => operands,
+        None => return Ok(()),
+    };
+    let anomaly = match result {
+        Value::Number(n) if n.is_nan() && !lhs.is_nan() && !rhs.is_nan() => Some("produced NaN"),
+        Value::Number(n) if n.is_infinite() && lhs.is_finite() && rhs.is_finite() => {
+            Some("overflowed to infinity")
+        }
+        Value::Number(n)
+            if *n == 0.0
+                && lhs != 0.0
+                && rhs.is_finite()
+                && matches!(
+                    op.kind,
+                    TokenKind::Slash | TokenKind::Star | TokenKind::StarStar
+                ) =>
+        {
+            Some("underflowed to zero")
+        }
+        Value::Number(_)
+            if both_ints
+                && (matches!(
+                    op.kind,
+                    TokenKind::Minus | TokenKind::Plus | TokenKind::Star
+                ) || matches!(op.kind, TokenKind::StarStar) && rhs >= 0.0) =>
+        {
+            Some("overflowed the integer range and lost precision")
+        }
+        Value::Bool(_)
+            if matches!(
+                op.kind,
+                TokenKind::BangEqual
+                    | TokenKind::EqualEqual
+                    | TokenKind::Greater
+                    | TokenKind::GreaterEqual
+                    | TokenKind::Less
+                    | TokenKind::LessEqual
+            ) && lhs != rhs
+                && (lhs - rhs).abs() <= 4.0 * f64::EPSILON * lhs.abs().max(rhs.abs()) =>
+        {
+            Some("compared numbers that differ only by rounding error")
+        }
+        _ => None,
+    };
+    match anomaly {
+        Some(anomaly) => Err(RuntimeError {
+            message: format!(
+                "Arithmetic check failed: {} {} {} {}",
+                lhs, op.lexeme, rhs, anomaly
+            ),
+            loc: op.loc.clone(),
+        }),
+        None => Ok(()),
+    }
+}
+
+fn expect_map_key(token: &Token, key: Value) -> Result<MapKey, RuntimeError> {
+    match MapKey::from_value(&key) {
+        Some(key) => Ok(key),
+        None => Err(RuntimeError {
+            message: format!(
+                "Map keys must be strings or numbers (and not NaN), got {}",
+                stringify(key)
+            ),
+            loc: token.loc.clone(),
+        }),
+    }
+}
+
+fn unary(op: &Token, right: Value) -> Result<Value, RuntimeError> {
+    match op {
+        Token {
+            kind: TokenKind::Minus,
+            ..
+        } => match right {
+            Value::Int(rhs) if rhs != i64::MIN => Ok(Value::Int(-rhs)),
+            right => {
+                let rhs = expect_number(op, right)?;
+                Ok(Value::Number(-rhs))
             }
+        },
+        Token {
+            kind: TokenKind::Bang,
+            ..
+        } => Ok(Value::Bool(!is_truthy(right))),
+        Token {
+            kind: TokenKind::Tilde,
+            ..
+        } => {
+            let rhs = expect_integer(op, right)?;
+            Ok(Value::Int(!rhs))
         }
+        tok => Err(RuntimeError {
+            message: String::from("invalid unary operator?"),
+            loc: tok.loc.clone(),
+        }),
     }
 }
 
-fn expect_number(op: Token, rhs: Option<LiteralValue>) -> Result<f64, RuntimeError> {
-    match rhs {
-        Some(LiteralValue::Number(rhs)) => Ok(rhs),
-        _ => Err(RuntimeError {
+fn as_number(value: &Value) -> Option<f64> {
+    match value {
+        Value::Int(n) => Some(*n as f64),
+        Value::Number(n) => Some(*n),
+        _ => None,
+    }
+}
+
+fn expect_number(op: &Token, rhs: Value) -> Result<f64, RuntimeError> {
+    match as_number(&rhs) {
+        Some(rhs) => Ok(rhs),
+        None => Err(RuntimeError {
             message: format!("Unary operator {} expects a numeric operand", op.lexeme),
-            loc: op.loc,
+            loc: op.loc.clone(),
         }),
     }
 }
 
-fn expect_numbers(
-    lhs: Option<LiteralValue>,
-    op: Token,
-    rhs: Option<LiteralValue>,
-) -> Result<(f64, f64), RuntimeError> {
-    match (lhs, rhs) {
-        (Some(LiteralValue::Number(lhs)), Some(LiteralValue::Number(rhs))) => Ok((lhs, rhs)),
+fn expect_numbers(lhs: Value, op: &Token, rhs: Value) -> Result<(f64, f64), RuntimeError> {
+    match (as_number(&lhs), as_number(&rhs)) {
+        (Some(lhs), Some(rhs)) => Ok((lhs, rhs)),
         (_, _) => Err(RuntimeError {
             message: format!("Binary operator {} expects two numeric operands", op.lexeme),
-            loc: op.loc,
+            loc: op.loc.clone(),
         }),
     }
 }
 
-fn is_equal(lhs: Option<LiteralValue>, rhs: Option<LiteralValue>) -> bool {
-    match (lhs, rhs) {
-        (None, None) => true,
-        (None, _) | (_, None) => false,
-        (Some(lhs), Some(rhs)) => lhs == rhs,
+/// Bitwise operators work on the integer part of their operands
+fn as_integer(value: &Value) -> Option<i64> {
+    match value {
+        Value::Int(n) => Some(*n),
+        Value::Number(n) => Some(*n as i64),
+        _ => None,
     }
 }
 
-fn is_truthy(expr: Option<LiteralValue>) -> bool {
+fn expect_integer(op: &Token, rhs: Value) -> Result<i64, RuntimeError> {
+    match as_integer(&rhs) {
+        Some(rhs) => Ok(rhs),
+        None => Err(RuntimeError {
+            message: format!("Unary operator {} expects a numeric operand", op.lexeme),
+            loc: op.loc.clone(),
+        }),
+    }
+}
+
+fn expect_integers(lhs: Value, op: &Token, rhs: Value) -> Result<(i64, i64), RuntimeError> {
+    match (as_integer(&lhs), as_integer(&rhs)) {
+        (Some(lhs), Some(rhs)) => Ok((lhs, rhs)),
+        (_, _) => Err(RuntimeError {
+            message: format!("Binary operator {} expects two numeric operands", op.lexeme),
+            loc: op.loc.clone(),
+        }),
+    }
+}
+
+/// Range bounds must be integers, though integral floats like `1e3` are accepted
+fn expect_range_bounds(lhs: Value, op: &Token, rhs: Value) -> Result<(i64, i64), RuntimeError> {
+    match (MapKey::from_value(&lhs), MapKey::from_value(&rhs)) {
+        (Some(MapKey::Int(lhs)), Some(MapKey::Int(rhs))) => Ok((lhs, rhs)),
+        (_, _) => Err(RuntimeError {
+            message: format!(
+                "Operator {} expects two integer bounds, got {} and {}",
+                op.lexeme,
+                stringify(lhs),
+                stringify(rhs)
+            ),
+            loc: op.loc.clone(),
+        }),
+    }
+}
+
+fn expect_shift_amount(op: &Token, amount: i64) -> Result<u32, RuntimeError> {
+    match amount {
+        0..=63 => Ok(amount as u32),
+        _ => Err(RuntimeError {
+            message: format!(
+                "Operator {} expects a shift amount between 0 and 63, got {}",
+                op.lexeme, amount
+            ),
+            loc: op.loc.clone(),
+        }),
+    }
+}
+
+pub(crate) fn is_truthy(expr: Value) -> bool {
     match expr {
-        Some(LiteralValue::Bool(boolean)) => boolean,
-        None => false,
+        Value::Bool(boolean) => boolean,
+        Value::Nil => false,
         _ => true,
     }
 }
 
-fn stringify(value: Option<LiteralValue>) -> String {
-    match value {
-        None => String::from("nil"),
-        Some(LiteralValue::Bool(b)) => b.to_string(),
-        Some(LiteralValue::Number(n)) => n.to_string(),
-        Some(LiteralValue::String(s)) => s,
+/// How `print` shows a value. Nested lists and maps are walked with an explicit stack,
+/// so no depth of nesting can overflow.
+pub fn stringify(value: Value) -> String {
+    let mut text = String::new();
+    let mut pending = vec![Piece::Value(&value)];
+    while let Some(piece) = pending.pop() {
+        let value = match piece {
+            Piece::Text(s) => {
+                text.push_str(s);
+                continue;
+            }
+            Piece::Key(MapKey::Int(n)) => {
+                text.push_str(&n.to_string());
+                continue;
+            }
+            Piece::Key(MapKey::Number(n)) => {
+                text.push_str(&format_number(*n));
+                continue;
+            }
+            Piece::Key(MapKey::String(s)) => {
+                text.push_str(s);
+                continue;
+            }
+            Piece::Value(value) => value,
+        };
+        match value {
+            Value::Nil => text.push_str("nil"),
+            Value::Bool(b) => text.push_str(&b.to_string()),
+            Value::Int(n) => text.push_str(&n.to_string()),
+            Value::List(elements) => {
+                text.push('[');
+                pending.push(Piece::Text("]"));
+                for (i, element) in elements.iter().enumerate().rev() {
+                    pending.push(Piece::Value(element));
+                    if i > 0 {
+                        pending.push(Piece::Text(", "));
+                    }
+                }
+            }
+            Value::Map(map) => {
+                text.push('{');
+                pending.push(Piece::Text("}"));
+                for (i, (key, value)) in map.iter().enumerate().rev() {
+                    pending.push(Piece::Value(value));
+                    pending.push(Piece::Text(": "));
+                    pending.push(Piece::Key(key));
+                    if i > 0 {
+                        pending.push(Piece::Text(", "));
+                    }
+                }
+            }
+            Value::Function(function) => text.push_str(&format!("{:?}", function)),
+            Value::Number(n) => text.push_str(&format_number(*n)),
+            Value::Range(range) => text.push_str(&format!(
+                "{}{}{}",
+                range.start,
+                if range.inclusive { "..=" } else { ".." },
+                range.end
+            )),
+            Value::String(s) => text.push_str(s),
+        }
+    }
+    text
+}
+
+/// Something `stringify` has left to write
+enum Piece<'a> {
+    Value(&'a Value),
+    Key(&'a MapKey),
+    Text(&'static str),
+}
+
+/// Formats floats like jlox, which prints Java's `Double.toString` without a trailing
+/// `.0`: the shortest digits that read back the same, in scientific notation below 1e-3
+/// and from 1e7 on (`1.0E21`, `2.5E-4`).
+fn format_number(n: f64) -> String {
+    if n.is_nan() {
+        return String::from("NaN");
+    }
+    if n.is_infinite() {
+        return String::from(if n > 0.0 { "Infinity" } else { "-Infinity" });
+    }
+    if n == 0.0 || (1e-3..1e7).contains(&n.abs()) {
+        return n.to_string();
+    }
+    let scientific = format!("{:e}", n);
+    let (mantissa, exponent) = scientific.split_once('e').unwrap_or((&scientific, "0"));
+    if mantissa.contains('.') {
+        format!("{}E{}", mantissa, exponent)
+    } else {
+        format!("{}.0E{}", mantissa, exponent)
     }
 }
@@ -1,30 +1,55 @@
+use std::cell::RefCell;
 use std::iter::Peekable;
+use std::num::IntErrorKind;
+
+#[cfg(feature = "unicode")]
 use unicode_segmentation::{Graphemes, UnicodeSegmentation};
 
+use crate::symbol::{Interner, Symbol};
+
+// Without the `unicode` feature the scanner walks `char`s instead of grapheme clusters
+#[cfg(not(feature = "unicode"))]
+type Graphemes<'a> = std::str::SplitInclusive<'a, fn(char) -> bool>;
+
 #[derive(Clone, Debug)]
 pub enum TokenKind {
     // Single-character tokens
+    Ampersand,
+    Caret,
+    Colon,
     Comma,
     Dot,
     LeftBrace,
+    LeftBracket,
     LeftParen,
     Minus,
+    Percent,
+    Pipe,
     Plus,
+    Question,
     RightBrace,
+    RightBracket,
     RightParen,
     Semicolon,
     Slash,
     Star,
+    Tilde,
 
     // Operators
     Bang,
     BangEqual,
+    DotDot,
+    DotDotEqual,
     Equal,
     EqualEqual,
     Greater,
     GreaterEqual,
+    GreaterGreater,
     Less,
     LessEqual,
+    LessLess,
+    QuestionQuestion,
+    StarStar,
 
     // Literals
     Identifier,
@@ -33,8 +58,10 @@ pub enum TokenKind {
 
     // Keywords
     And,
+    Assert,
     Class,
     Else,
+    Eprint,
     False,
     For,
     Fun,
@@ -50,15 +77,18 @@ pub enum TokenKind {
     While,
 
     // Misc and whitespace
+    BlockComment,
     Comment,
     Eof,
     NewLine,
     Whitespace,
 }
 
+/// The value a literal token stands for; `nil` has none
 #[derive(Clone, Debug, PartialEq)]
 pub enum LiteralValue {
     Bool(bool),
+    Int(i64),
     Number(f64),
     String(String),
 }
@@ -86,6 +116,17 @@ impl Loc {
     }
 }
 
+/// `line 3`, or `lines 3-5` for a token spanning several lines
+impl std::fmt::Display for Loc {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        if self.is_single() {
+            write!(f, "line {}", self.line_begin)
+        } else {
+            write!(f, "lines {}-{}", self.line_begin, self.line_end)
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct LexingError {
     message: String,
@@ -93,27 +134,93 @@ pub struct LexingError {
     loc: Loc,
 }
 
+impl std::fmt::Display for LexingError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match &self.lexeme {
+            Some(lexeme) => write!(f, "[{}] Error at '{}': {}", self.loc, lexeme, self.message),
+            None => write!(f, "[{}] Error: {}", self.loc, self.message),
+        }
+    }
+}
+
+impl std::error::Error for LexingError {}
+
+impl LexingError {
+    /// Whether scanning failed only because the source ended inside a string or block
+    /// comment, which are the only errors reported without an offending lexeme
+    pub fn is_unexpected_eof(&self) -> bool {
+        self.lexeme.is_none()
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct Token {
     pub kind: TokenKind,
-    pub lexeme: String,
+    pub lexeme: Symbol,
     pub literal: Option<LiteralValue>,
     pub loc: Loc,
 }
 
+/// The units the scanner works on: grapheme clusters in general, or single bytes when the
+/// source is ASCII, which is much cheaper and splits the source the same way
+#[derive(Clone)]
+enum Segments<'a> {
+    Ascii(&'a str),
+    Graphemes(Graphemes<'a>),
+}
+
+impl<'a> Iterator for Segments<'a> {
+    type Item = &'a str;
+
+    fn next(&mut self) -> Option<&'a str> {
+        match self {
+            Segments::Ascii(rest) => {
+                if rest.is_empty() {
+                    return None;
+                }
+                // Grapheme segmentation keeps "\r\n" together, so this does too
+                let len = if cfg!(feature = "unicode") && rest.starts_with("\r\n") {
+                    2
+                } else {
+                    1
+                };
+                let (segment, remaining) = rest.split_at(len);
+                *rest = remaining;
+                Some(segment)
+            }
+            Segments::Graphemes(graphemes) => graphemes.next(),
+        }
+    }
+}
+
 #[derive(Debug)]
-pub struct Scanner {
-    source: String,
+pub struct Scanner<'a> {
+    source: &'a str,
+    ascii_fast_path: bool,
+    interner: RefCell<Interner>,
 }
 
-impl Scanner {
-    pub fn new(source: String) -> Scanner {
-        Scanner { source: source }
+impl<'a> Scanner<'a> {
+    pub fn new(source: &'a str) -> Scanner<'a> {
+        Scanner {
+            source,
+            ascii_fast_path: true,
+            interner: RefCell::default(),
+        }
+    }
+
+    /// Always segments the source into graphemes, even when it is ASCII.
+    /// Only useful to compare both paths in tests and benchmarks.
+    pub fn without_ascii_fast_path(self) -> Scanner<'a> {
+        Scanner {
+            ascii_fast_path: false,
+            ..self
+        }
     }
 
-    pub fn scan(&mut self) -> Result<Vec<Token>, LexingError> {
+    pub fn scan(&self) -> Result<Vec<Token>, LexingError> {
         let mut current_line: usize = 1;
-        let mut graphemes_iter = self.source.graphemes(true).peekable();
+        let mut graphemes_iter = self.segments().peekable();
         let mut tokens: Vec<Token> = Vec::new();
         loop {
             match self.parse_token(&mut graphemes_iter, current_line)? {
@@ -121,6 +228,11 @@ impl Scanner {
                     kind: TokenKind::Whitespace,
                     ..
                 } => (),
+                Token {
+                    kind: TokenKind::BlockComment,
+                    loc,
+                    ..
+                } => current_line += loc.offset(),
                 Token {
                     kind: TokenKind::Comment,
                     ..
@@ -144,9 +256,80 @@ impl Scanner {
         Ok(tokens)
     }
 
+    /// The lexeme for `text`, shared with every other token spelled the same way
+    fn symbol(&self, text: &str) -> Symbol {
+        self.interner.borrow_mut().intern(text)
+    }
+
+    fn segments(&self) -> Segments<'a> {
+        if self.ascii_fast_path && self.source.is_ascii() {
+            Segments::Ascii(self.source)
+        } else {
+            Segments::Graphemes(Scanner::graphemes(self.source))
+        }
+    }
+
+    #[cfg(feature = "unicode")]
+    pub(crate) fn graphemes(source: &str) -> Graphemes<'_> {
+        source.graphemes(true)
+    }
+
+    #[cfg(not(feature = "unicode"))]
+    pub(crate) fn graphemes(source: &str) -> Graphemes<'_> {
+        source.split_inclusive((|_| true) as fn(char) -> bool)
+    }
+
+    fn parse_block_comment(
+        &self,
+        graphemes_iter: &mut Peekable<Segments>,
+        line_begin: usize,
+    ) -> Result<Token, LexingError> {
+        let mut line_current = line_begin;
+        let mut depth: usize = 1;
+        let mut string = vec![String::from("/*")];
+        while depth > 0 {
+            let grapheme1 = graphemes_iter.next();
+            let grapheme2 = graphemes_iter.peek();
+            let literal = match (grapheme1, grapheme2) {
+                (None, _) => {
+                    return Err(LexingError {
+                        message: String::from("Unexpected EOF in unterminated block comment"),
+                        lexeme: None,
+                        loc: Loc::single(line_current),
+                    })
+                }
+                (Some("/"), Some(&"*")) => {
+                    graphemes_iter.next();
+                    depth += 1;
+                    "/*"
+                }
+                (Some("*"), Some(&"/")) => {
+                    graphemes_iter.next();
+                    depth -= 1;
+                    "*/"
+                }
+                (Some(l @ "\n"), _) | (Some(l @ "\r\n"), _) => {
+                    line_current += 1;
+                    l
+                }
+                (Some(l), _) => l,
+            };
+            string.push(String::from(literal));
+        }
+        Ok(Token {
+            kind: TokenKind::BlockComment,
+            lexeme: string.concat().into(),
+            literal: None,
+            loc: Loc {
+                line_begin,
+                line_end: line_current,
+            },
+        })
+    }
+
     fn parse_identifier(
         &self,
-        graphemes_iter: &mut Peekable<Graphemes>,
+        graphemes_iter: &mut Peekable<Segments>,
         first_char: &str,
         current_line: usize,
     ) -> Token {
@@ -158,25 +341,39 @@ impl Scanner {
             string.push(String::from(graphemes_iter.next().unwrap()));
         }
         let string = string.concat();
-        Scanner::keyword_or_identifier_token(string.as_str(), current_line)
+        self.keyword_or_identifier_token(string.as_str(), current_line)
     }
 
     fn parse_number_literal(
         &self,
-        graphemes_iter: &mut Peekable<Graphemes>,
+        graphemes_iter: &mut Peekable<Segments>,
         first_digit: &str,
         current_line: usize,
     ) -> Result<Token, LexingError> {
+        if first_digit == "0" {
+            let radix = match graphemes_iter.peek() {
+                Some(&"x") | Some(&"X") => Some(16),
+                Some(&"b") | Some(&"B") => Some(2),
+                _ => None,
+            };
+            if let Some(radix) = radix {
+                return self.parse_radix_literal(graphemes_iter, radix, current_line);
+            }
+        }
         let mut string = vec![String::from(first_digit)];
         let mut has_point = first_digit == ".";
         loop {
+            // `1..2` is a range, not the number `1.` followed by `.2`
+            if Scanner::starts_range(graphemes_iter) {
+                break;
+            }
             let literal = match graphemes_iter.peek() {
                 Some(g) if Scanner::is_digit(g) => graphemes_iter.next().unwrap(),
                 Some(&".") => {
                     if has_point {
                         return Err(LexingError {
                             message: String::from(
-                                "Unexpected additional point while parsing number at line {}",
+                                "Unexpected additional point while parsing number",
                             ),
                             lexeme: Some(String::from(".")),
                             loc: Loc::single(current_line),
@@ -189,18 +386,119 @@ impl Scanner {
             };
             string.push(String::from(literal));
         }
+        let has_exponent = Scanner::has_exponent(graphemes_iter);
+        if has_exponent {
+            string.push(String::from(graphemes_iter.next().unwrap()));
+            if let Some(&"+") | Some(&"-") = graphemes_iter.peek() {
+                string.push(String::from(graphemes_iter.next().unwrap()));
+            }
+            while let Some(g) = graphemes_iter.peek() {
+                if !Scanner::is_digit(g) {
+                    break;
+                }
+                string.push(String::from(graphemes_iter.next().unwrap()));
+            }
+        }
         let string = string.concat();
+        // Literals without a point or exponent are integers, unless they are too large for one
+        let literal = match string.parse::<i64>() {
+            Ok(int) if !has_point && !has_exponent => LiteralValue::Int(int),
+            _ => match string.parse::<f64>() {
+                // Rather than silently reading `1e400` as infinity
+                Ok(number) if number.is_infinite() => {
+                    return Err(LexingError {
+                        message: String::from("Number literal too large"),
+                        lexeme: Some(string),
+                        loc: Loc::single(current_line),
+                    })
+                }
+                Ok(number) => LiteralValue::Number(number),
+                Err(_) => {
+                    return Err(LexingError {
+                        message: String::from("Invalid number literal"),
+                        lexeme: Some(string),
+                        loc: Loc::single(current_line),
+                    })
+                }
+            },
+        };
         Ok(Token {
             kind: TokenKind::Number,
-            lexeme: string.clone(),
-            literal: Some(LiteralValue::Number(string.parse::<f64>().unwrap())),
+            lexeme: self.symbol(&string),
+            literal: Some(literal),
+            loc: Loc::single(current_line),
+        })
+    }
+
+    /// Whether the next two segments are `..`
+    fn starts_range(graphemes_iter: &Peekable<Segments>) -> bool {
+        let mut lookahead = graphemes_iter.clone();
+        lookahead.next() == Some(".") && lookahead.next() == Some(".")
+    }
+
+    /// Whether the number being scanned continues with an exponent like `e3`, `E-3` or `e+3`.
+    /// Only looks ahead, so `1else` still scans as `1` followed by an identifier.
+    fn has_exponent(graphemes_iter: &Peekable<Segments>) -> bool {
+        let mut lookahead = graphemes_iter.clone();
+        if !matches!(lookahead.next(), Some("e") | Some("E")) {
+            return false;
+        }
+        if let Some(&"+") | Some(&"-") = lookahead.peek() {
+            lookahead.next();
+        }
+        matches!(lookahead.peek(), Some(g) if Scanner::is_digit(g))
+    }
+
+    /// Scans `0x` and `0b` literals, after their leading zero
+    fn parse_radix_literal(
+        &self,
+        graphemes_iter: &mut Peekable<Segments>,
+        radix: u32,
+        current_line: usize,
+    ) -> Result<Token, LexingError> {
+        let prefix = graphemes_iter.next().unwrap();
+        let mut digits: Vec<String> = Vec::new();
+        // Consume trailing letters and digits too, so `0b102` is an error instead of `0b10` `2`
+        while let Some(g) = graphemes_iter.peek() {
+            if !Scanner::is_ident_trailing(g) {
+                break;
+            }
+            digits.push(String::from(graphemes_iter.next().unwrap()));
+        }
+        let digits = digits.concat();
+        let lexeme = [String::from("0"), String::from(prefix), digits.clone()].concat();
+        let literal = match (
+            i64::from_str_radix(&digits, radix),
+            u128::from_str_radix(&digits, radix),
+        ) {
+            (Ok(int), _) => LiteralValue::Int(int),
+            (_, Ok(large)) => LiteralValue::Number(large as f64),
+            (_, Err(error)) if *error.kind() == IntErrorKind::PosOverflow => {
+                return Err(LexingError {
+                    message: format!("Base {} number literal too large", radix),
+                    lexeme: Some(lexeme),
+                    loc: Loc::single(current_line),
+                })
+            }
+            (_, Err(_)) => {
+                return Err(LexingError {
+                    message: format!("Invalid digits in base {} number literal", radix),
+                    lexeme: Some(lexeme),
+                    loc: Loc::single(current_line),
+                })
+            }
+        };
+        Ok(Token {
+            kind: TokenKind::Number,
+            lexeme: self.symbol(&lexeme),
+            literal: Some(literal),
             loc: Loc::single(current_line),
         })
     }
 
     fn parse_str_literal(
         &self,
-        graphemes_iter: &mut Peekable<Graphemes>,
+        graphemes_iter: &mut Peekable<Segments>,
         line_begin: usize,
     ) -> Result<Token, LexingError> {
         let mut line_current = line_begin;
@@ -209,7 +507,7 @@ impl Scanner {
             let grapheme1 = graphemes_iter.next();
             let grapheme2 = graphemes_iter.peek();
             let literal = match (grapheme1, grapheme2) {
-                (Some("\n"), None) | (None, _) => {
+                (Some("\n"), None) | (Some("\r\n"), None) | (None, _) => {
                     return Err(LexingError {
                         message: String::from("Unexpected EOF in unterminated string"),
                         lexeme: None,
@@ -220,9 +518,9 @@ impl Scanner {
                     graphemes_iter.next();
                     "\\\""
                 }
-                (Some("\n"), _) => {
+                (Some(l @ "\n"), _) | (Some(l @ "\r\n"), _) => {
                     line_current += 1;
-                    "\n"
+                    l
                 }
                 (Some("\""), _) => {
                     break;
@@ -234,10 +532,10 @@ impl Scanner {
         let string = string.concat();
         Ok(Token {
             kind: TokenKind::String,
-            lexeme: [String::from("\""), string.clone(), String::from("\"")].concat(),
+            lexeme: self.symbol(&["\"", string.as_str(), "\""].concat()),
             literal: Some(LiteralValue::String(string)),
             loc: Loc {
-                line_begin: line_begin,
+                line_begin,
                 line_end: line_current,
             },
         })
@@ -245,7 +543,7 @@ impl Scanner {
 
     fn parse_token(
         &self,
-        graphemes_iter: &mut Peekable<Graphemes>,
+        graphemes_iter: &mut Peekable<Segments>,
         current_line: usize,
     ) -> Result<Token, LexingError> {
         let grapheme1 = graphemes_iter.next();
@@ -253,7 +551,7 @@ impl Scanner {
         let token = match grapheme1 {
             None => Token {
                 kind: TokenKind::Eof,
-                lexeme: String::from("\0"),
+                lexeme: self.symbol("\0"),
                 literal: None,
                 loc: Loc::single(current_line),
             },
@@ -264,10 +562,28 @@ impl Scanner {
             l @ Some(".") => {
                 if grapheme2.is_some() && Scanner::is_digit(grapheme2.unwrap()) {
                     self.parse_number_literal(graphemes_iter, l.unwrap(), current_line)?
+                } else if grapheme2 == Some(&".") {
+                    graphemes_iter.next();
+                    if graphemes_iter.peek() == Some(&"=") {
+                        graphemes_iter.next();
+                        Token {
+                            kind: TokenKind::DotDotEqual,
+                            lexeme: self.symbol("..="),
+                            literal: None,
+                            loc: Loc::single(current_line),
+                        }
+                    } else {
+                        Token {
+                            kind: TokenKind::DotDot,
+                            lexeme: self.symbol(".."),
+                            literal: None,
+                            loc: Loc::single(current_line),
+                        }
+                    }
                 } else {
                     Token {
                         kind: TokenKind::Dot,
-                        lexeme: String::from(l.unwrap()),
+                        lexeme: self.symbol(l.unwrap()),
                         literal: None,
                         loc: Loc::single(current_line),
                     }
@@ -278,155 +594,252 @@ impl Scanner {
             }
             l @ Some(" ") | l @ Some("\r") | l @ Some("\t") => Token {
                 kind: TokenKind::Whitespace,
-                lexeme: String::from(l.unwrap()),
+                lexeme: self.symbol(l.unwrap()),
                 literal: None,
                 loc: Loc::single(current_line),
             },
             l @ Some("(") => Token {
                 kind: TokenKind::LeftParen,
-                lexeme: String::from(l.unwrap()),
+                lexeme: self.symbol(l.unwrap()),
                 literal: None,
                 loc: Loc::single(current_line),
             },
             l @ Some(")") => Token {
                 kind: TokenKind::RightParen,
-                lexeme: String::from(l.unwrap()),
+                lexeme: self.symbol(l.unwrap()),
                 literal: None,
                 loc: Loc::single(current_line),
             },
             l @ Some("{") => Token {
                 kind: TokenKind::LeftBrace,
-                lexeme: String::from(l.unwrap()),
+                lexeme: self.symbol(l.unwrap()),
                 literal: None,
                 loc: Loc::single(current_line),
             },
             l @ Some("}") => Token {
                 kind: TokenKind::RightBrace,
-                lexeme: String::from(l.unwrap()),
+                lexeme: self.symbol(l.unwrap()),
+                literal: None,
+                loc: Loc::single(current_line),
+            },
+            l @ Some("[") => Token {
+                kind: TokenKind::LeftBracket,
+                lexeme: self.symbol(l.unwrap()),
+                literal: None,
+                loc: Loc::single(current_line),
+            },
+            l @ Some("]") => Token {
+                kind: TokenKind::RightBracket,
+                lexeme: self.symbol(l.unwrap()),
+                literal: None,
+                loc: Loc::single(current_line),
+            },
+            l @ Some("&") => Token {
+                kind: TokenKind::Ampersand,
+                lexeme: self.symbol(l.unwrap()),
+                literal: None,
+                loc: Loc::single(current_line),
+            },
+            l @ Some("^") => Token {
+                kind: TokenKind::Caret,
+                lexeme: self.symbol(l.unwrap()),
+                literal: None,
+                loc: Loc::single(current_line),
+            },
+            l @ Some(":") => Token {
+                kind: TokenKind::Colon,
+                lexeme: self.symbol(l.unwrap()),
                 literal: None,
                 loc: Loc::single(current_line),
             },
             l @ Some(",") => Token {
                 kind: TokenKind::Comma,
-                lexeme: String::from(l.unwrap()),
+                lexeme: self.symbol(l.unwrap()),
                 literal: None,
                 loc: Loc::single(current_line),
             },
             l @ Some("-") => Token {
                 kind: TokenKind::Minus,
-                lexeme: String::from(l.unwrap()),
+                lexeme: self.symbol(l.unwrap()),
+                literal: None,
+                loc: Loc::single(current_line),
+            },
+            l @ Some("%") => Token {
+                kind: TokenKind::Percent,
+                lexeme: self.symbol(l.unwrap()),
+                literal: None,
+                loc: Loc::single(current_line),
+            },
+            l @ Some("|") => Token {
+                kind: TokenKind::Pipe,
+                lexeme: self.symbol(l.unwrap()),
                 literal: None,
                 loc: Loc::single(current_line),
             },
             l @ Some("+") => Token {
                 kind: TokenKind::Plus,
-                lexeme: String::from(l.unwrap()),
+                lexeme: self.symbol(l.unwrap()),
                 literal: None,
                 loc: Loc::single(current_line),
             },
+            l @ Some("?") => {
+                if grapheme2 == Some(&"?") {
+                    graphemes_iter.next();
+                    Token {
+                        kind: TokenKind::QuestionQuestion,
+                        lexeme: self.symbol("??"),
+                        literal: None,
+                        loc: Loc::single(current_line),
+                    }
+                } else {
+                    Token {
+                        kind: TokenKind::Question,
+                        lexeme: self.symbol(l.unwrap()),
+                        literal: None,
+                        loc: Loc::single(current_line),
+                    }
+                }
+            }
             l @ Some(";") => Token {
                 kind: TokenKind::Semicolon,
-                lexeme: String::from(l.unwrap()),
+                lexeme: self.symbol(l.unwrap()),
                 literal: None,
                 loc: Loc::single(current_line),
             },
-            l @ Some("*") => Token {
-                kind: TokenKind::Star,
-                lexeme: String::from(l.unwrap()),
+            l @ Some("*") => {
+                if grapheme2 == Some(&"*") {
+                    graphemes_iter.next();
+                    Token {
+                        kind: TokenKind::StarStar,
+                        lexeme: self.symbol("**"),
+                        literal: None,
+                        loc: Loc::single(current_line),
+                    }
+                } else {
+                    Token {
+                        kind: TokenKind::Star,
+                        lexeme: self.symbol(l.unwrap()),
+                        literal: None,
+                        loc: Loc::single(current_line),
+                    }
+                }
+            }
+            l @ Some("~") => Token {
+                kind: TokenKind::Tilde,
+                lexeme: self.symbol(l.unwrap()),
                 literal: None,
                 loc: Loc::single(current_line),
             },
-            l @ Some("\n") => Token {
+            l @ Some("\n") | l @ Some("\r\n") => Token {
                 kind: TokenKind::NewLine,
-                lexeme: String::from(l.unwrap()),
+                lexeme: self.symbol(l.unwrap()),
                 literal: None,
                 loc: Loc::single(current_line),
             },
             l @ Some("!") => {
-                if grapheme2 == Some(&&"=") {
+                if grapheme2 == Some(&"=") {
                     graphemes_iter.next();
                     Token {
                         kind: TokenKind::BangEqual,
-                        lexeme: String::from("!="),
+                        lexeme: self.symbol("!="),
                         literal: None,
                         loc: Loc::single(current_line),
                     }
                 } else {
                     Token {
                         kind: TokenKind::Bang,
-                        lexeme: String::from(l.unwrap()),
+                        lexeme: self.symbol(l.unwrap()),
                         literal: None,
                         loc: Loc::single(current_line),
                     }
                 }
             }
             l @ Some("=") => {
-                if grapheme2 == Some(&&"=") {
+                if grapheme2 == Some(&"=") {
                     graphemes_iter.next();
                     Token {
                         kind: TokenKind::EqualEqual,
-                        lexeme: String::from("=="),
+                        lexeme: self.symbol("=="),
                         literal: None,
                         loc: Loc::single(current_line),
                     }
                 } else {
                     Token {
                         kind: TokenKind::Equal,
-                        lexeme: String::from(l.unwrap()),
+                        lexeme: self.symbol(l.unwrap()),
                         literal: None,
                         loc: Loc::single(current_line),
                     }
                 }
             }
             l @ Some("<") => {
-                if grapheme2 == Some(&&"=") {
+                if grapheme2 == Some(&"=") {
                     graphemes_iter.next();
                     Token {
                         kind: TokenKind::LessEqual,
-                        lexeme: String::from("<="),
+                        lexeme: self.symbol("<="),
+                        literal: None,
+                        loc: Loc::single(current_line),
+                    }
+                } else if grapheme2 == Some(&"<") {
+                    graphemes_iter.next();
+                    Token {
+                        kind: TokenKind::LessLess,
+                        lexeme: self.symbol("<<"),
                         literal: None,
                         loc: Loc::single(current_line),
                     }
                 } else {
                     Token {
                         kind: TokenKind::Less,
-                        lexeme: String::from(l.unwrap()),
+                        lexeme: self.symbol(l.unwrap()),
                         literal: None,
                         loc: Loc::single(current_line),
                     }
                 }
             }
             l @ Some(">") => {
-                if grapheme2 == Some(&&"=") {
+                if grapheme2 == Some(&"=") {
                     graphemes_iter.next();
                     Token {
                         kind: TokenKind::GreaterEqual,
-                        lexeme: String::from(">="),
+                        lexeme: self.symbol(">="),
+                        literal: None,
+                        loc: Loc::single(current_line),
+                    }
+                } else if grapheme2 == Some(&">") {
+                    graphemes_iter.next();
+                    Token {
+                        kind: TokenKind::GreaterGreater,
+                        lexeme: self.symbol(">>"),
                         literal: None,
                         loc: Loc::single(current_line),
                     }
                 } else {
                     Token {
                         kind: TokenKind::Greater,
-                        lexeme: String::from(l.unwrap()),
+                        lexeme: self.symbol(l.unwrap()),
                         literal: None,
                         loc: Loc::single(current_line),
                     }
                 }
             }
             l @ Some("/") => {
-                if grapheme2 == Some(&&"/") {
+                if grapheme2 == Some(&"/") {
                     graphemes_iter.next();
                     Token {
                         kind: TokenKind::Comment,
-                        lexeme: String::from("//"),
+                        lexeme: self.symbol("//"),
                         literal: None,
                         loc: Loc::single(current_line),
                     }
+                } else if grapheme2 == Some(&"*") {
+                    graphemes_iter.next();
+                    self.parse_block_comment(graphemes_iter, current_line)?
                 } else {
                     Token {
                         kind: TokenKind::Slash,
-                        lexeme: String::from(l.unwrap()),
+                        lexeme: self.symbol(l.unwrap()),
                         literal: None,
                         loc: Loc::single(current_line),
                     }
@@ -443,15 +856,19 @@ impl Scanner {
         Ok(token)
     }
 
-    fn consume_line(&self, graphemes_iter: &mut Peekable<Graphemes>) {
-        while graphemes_iter.next() != None && graphemes_iter.peek() != Some(&"\n") {}
+    fn consume_line(&self, graphemes_iter: &mut Peekable<Segments>) {
+        while graphemes_iter.next().is_some()
+            && !matches!(graphemes_iter.peek(), Some(&"\n") | Some(&"\r\n"))
+        {}
     }
 
-    fn keyword_or_identifier_token(grapheme: &str, current_line: usize) -> Token {
+    fn keyword_or_identifier_token(&self, grapheme: &str, current_line: usize) -> Token {
         let kind = match grapheme {
             "and" => TokenKind::And,
+            "assert" => TokenKind::Assert,
             "class" => TokenKind::Class,
             "else" => TokenKind::Else,
+            "eprint" => TokenKind::Eprint,
             "false" => TokenKind::False,
             "for" => TokenKind::For,
             "fun" => TokenKind::Fun,
@@ -473,28 +890,76 @@ impl Scanner {
             _ => None,
         };
         Token {
-            kind: kind,
-            lexeme: String::from(grapheme),
-            literal: literal,
+            kind,
+            lexeme: self.symbol(grapheme),
+            literal,
             loc: Loc::single(current_line),
         }
     }
 
     fn is_digit(grapheme: &str) -> bool {
-        match grapheme {
-            "0" | "1" | "2" | "3" | "4" | "5" | "6" | "7" | "8" | "9" => true,
-            _ => false,
-        }
+        matches!(
+            grapheme,
+            "0" | "1" | "2" | "3" | "4" | "5" | "6" | "7" | "8" | "9"
+        )
     }
 
     fn is_ident_start(grapheme: &str) -> bool {
-        match grapheme {
-            "_" | "A" | "B" | "C" | "D" | "E" | "F" | "G" | "H" | "I" | "J" | "K" | "L" | "M"
-            | "N" | "O" | "P" | "Q" | "R" | "S" | "T" | "U" | "V" | "W" | "X" | "Y" | "Z" | "a"
-            | "b" | "c" | "d" | "e" | "f" | "g" | "h" | "i" | "j" | "k" | "l" | "m" | "n" | "o"
-            | "p" | "q" | "r" | "s" | "t" | "u" | "v" | "w" | "x" | "y" | "z" => true,
-            _ => false,
-        }
+        matches!(
+            grapheme,
+            "_" | "A"
+                | "B"
+                | "C"
+                | "D"
+                | "E"
+                | "F"
+                | "G"
+                | "H"
+                | "I"
+                | "J"
+                | "K"
+                | "L"
+                | "M"
+                | "N"
+                | "O"
+                | "P"
+                | "Q"
+                | "R"
+                | "S"
+                | "T"
+                | "U"
+                | "V"
+                | "W"
+                | "X"
+                | "Y"
+                | "Z"
+                | "a"
+                | "b"
+                | "c"
+                | "d"
+                | "e"
+                | "f"
+                | "g"
+                | "h"
+                | "i"
+                | "j"
+                | "k"
+                | "l"
+                | "m"
+                | "n"
+                | "o"
+                | "p"
+                | "q"
+                | "r"
+                | "s"
+                | "t"
+                | "u"
+                | "v"
+                | "w"
+                | "x"
+                | "y"
+                | "z"
+        )
     }
 
     fn is_ident_trailing(grapheme: &str) -> bool {
@@ -1,5 +1,16 @@
+pub mod callable;
+pub mod environment;
 pub mod expr;
+pub mod hooks;
 pub mod interpreter;
 pub mod lexing;
+pub mod natives;
+pub mod optimizing;
 pub mod parsing;
+pub mod program;
+pub mod snapshot;
 pub mod stmt;
+pub mod symbol;
+pub mod value;
+
+pub use program::run_no_panic;
@@ -1,28 +1,249 @@
-use std::io::{BufRead, Write};
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
 use clap::{AppSettings, Clap};
-use rlox::interpreter::interpret;
+use rlox::interpreter::{stringify, Halt, Interpreter, Options};
 use rlox::lexing::Scanner;
+use rlox::optimizing::{optimize, Stats};
 use rlox::parsing::parse;
 
+use crate::config::{Config, Repl, Settings};
+
+mod config;
+
 #[derive(Clap)]
 #[clap(version = env!("CARGO_PKG_VERSION"), author = env!("CARGO_PKG_AUTHORS"))]
 #[clap(setting = AppSettings::ColoredHelp)]
+#[clap(setting = AppSettings::NoAutoVersion)]
 struct Opts {
+    #[clap(subcommand)]
+    command: Option<Command>,
     /// Path of script to run
     file: Option<String>,
+    /// Arguments for the script, given after `--` and available to it as the list `args`
+    #[clap(last = true)]
+    args: Vec<String>,
+    /// Read settings from this file instead of the nearest .rlox.toml
+    #[clap(long)]
+    config: Option<PathBuf>,
+    /// Run this file before the script or REPL instead of ~/.rlox/prelude.lox
+    #[clap(long)]
+    preload: Option<PathBuf>,
+    /// Print version information
+    #[clap(short = 'V', long)]
+    version: bool,
+    /// With --version, report enabled features and language extensions
+    #[clap(long)]
+    verbose: bool,
+    /// With --version --verbose, print the report as JSON
+    #[clap(long)]
+    json: bool,
+    /// REPL prompt [default: "> "]; may use {line_no}, {duration_of_last} and {version}
+    #[clap(long)]
+    prompt: Option<String>,
+    /// REPL prompt shown while a statement spans several lines [default: "... "]
+    #[clap(long)]
+    continuation_prompt: Option<String>,
+    /// Text printed when the REPL starts; may use the same variables as --prompt
+    #[clap(long)]
+    banner: Option<String>,
+    /// In the REPL, restore globals from this file and save them back to it after each entry
+    #[clap(long)]
+    session: Option<PathBuf>,
+    /// Print optimizer statistics to stderr after parsing
+    #[clap(long)]
+    stats: bool,
+    /// Print the syntax tree to stderr after optimizing it
+    #[clap(long)]
+    ast: bool,
+    /// Raise runtime errors for NaN, overflow, underflow and imprecise float comparisons
+    #[clap(long)]
+    check_arith: bool,
+    /// Let `==` find a number equal to a string holding it, as in `1 == "1"`
+    #[clap(long)]
+    coerce_equality: bool,
+    /// Skip assert statements, e.g. for production runs
+    #[clap(long)]
+    disable_assertions: bool,
+    /// Reject `"a" + 1` instead of concatenating the number's printed form
+    #[clap(long)]
+    strict_concat: bool,
+    /// Raise runtime errors for division or remainder by zero instead of returning inf or NaN
+    #[clap(long)]
+    strict_math: bool,
+    /// Let httpGet() make requests; only a flag, so no config file can grant it
+    #[clap(long)]
+    allow_net: bool,
+}
+
+#[derive(Clap)]
+enum Command {
+    /// Inspect the configuration
+    Config {
+        #[clap(subcommand)]
+        command: ConfigCommand,
+    },
 }
 
+#[derive(Clap)]
+enum ConfigCommand {
+    /// Print the configuration in effect, after merging .rlox.toml with the flags given
+    Show,
+}
+
+/// Cargo features this binary was built with
+const FEATURES: &[(&str, bool)] = &[
+    ("cli", cfg!(feature = "cli")),
+    ("net", cfg!(feature = "net")),
+    ("unicode", cfg!(feature = "unicode")),
+];
+
+/// Syntax beyond the book's Lox that this build understands
+const EXTENSIONS: &[&str] = &[
+    "assert-statement",
+    "bitwise-operators",
+    "block-comments",
+    "conditional-operator",
+    "eprint-statement",
+    "exponent-operator",
+    "integers",
+    "lists",
+    "maps",
+    "mixed-concatenation",
+    "modulo-operator",
+    "nil-coalescing-operator",
+    "radix-and-exponent-literals",
+    "ranges",
+];
+
+/// Interpreter options that can be turned on from the command line
+const OPTIONS: &[&str] = &[
+    "allow-net",
+    "check-arith",
+    "coerce-equality",
+    "disable-assertions",
+    "strict-concat",
+    "strict-math",
+];
+
 fn main() {
     let opts: Opts = Opts::parse();
-    match opts.file {
-        Some(path) => run_file(path),
-        None => run_prompt(),
+    if opts.version {
+        print_version(&opts);
+        return;
+    }
+    let (path, config) = match load_config(&opts) {
+        Ok(found) => found,
+        Err(error) => {
+            eprintln!("Invalid config file {}", error);
+            std::process::exit(1);
+        }
+    };
+    let settings = config.merge(flags_config(&opts)).resolve();
+    let options = Options {
+        allow_net: opts.allow_net,
+        args: opts.args.clone(),
+        ..settings.options()
+    };
+    match (&opts.command, &opts.file) {
+        (
+            Some(Command::Config {
+                command: ConfigCommand::Show,
+            }),
+            _,
+        ) => {
+            if let Err(error) = show_config(path, &settings) {
+                eprintln!("Cannot show config: {}", error);
+                std::process::exit(1);
+            }
+        }
+        (None, Some(file)) => run_file(file, &opts, &settings, options),
+        (None, None) => run_prompt(&opts, &settings, options),
+    }
+}
+
+/// Reads the file given by `--config`, or else the nearest `.rlox.toml`, if any.
+fn load_config(opts: &Opts) -> Result<(Option<PathBuf>, Config), config::ConfigError> {
+    if let Some(path) = &opts.config {
+        return Ok((Some(path.clone()), Config::load(path)?));
+    }
+    // Without a working directory, say one that was deleted, there is nothing to discover
+    let cwd = match std::env::current_dir() {
+        Ok(cwd) => cwd,
+        Err(_) => return Ok((None, Config::default())),
+    };
+    match Config::discover(&cwd)? {
+        Some((path, config)) => Ok((Some(path), config)),
+        None => Ok((None, Config::default())),
+    }
+}
+
+/// Runs the file given by `--preload`, or else `~/.rlox/prelude.lox` if it exists
+fn run_prelude(opts: &Opts, settings: &Settings, interpreter: &mut Interpreter) {
+    let path = match &opts.preload {
+        Some(path) => path.clone(),
+        None => match home_dir() {
+            Some(home) => home.join(".rlox").join("prelude.lox"),
+            None => return,
+        },
+    };
+    let content = match std::fs::read_to_string(&path) {
+        Ok(content) => content,
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound && opts.preload.is_none() => {
+            return
+        }
+        Err(error) => {
+            eprintln!("Cannot read prelude {}: {}", path.display(), error);
+            std::process::exit(1);
+        }
+    };
+    if let Some(code) = run(&content, settings, interpreter, false) {
+        std::process::exit(code);
+    }
+}
+
+fn home_dir() -> Option<PathBuf> {
+    let home = if cfg!(windows) { "USERPROFILE" } else { "HOME" };
+    std::env::var_os(home)
+        .filter(|home| !home.is_empty())
+        .map(PathBuf::from)
+}
+
+/// The settings given as flags, which take precedence over the config file
+fn flags_config(opts: &Opts) -> Config {
+    Config {
+        check_arith: opts.check_arith.then_some(true),
+        coerce_equality: opts.coerce_equality.then_some(true),
+        disable_assertions: opts.disable_assertions.then_some(true),
+        strict_concat: opts.strict_concat.then_some(true),
+        strict_math: opts.strict_math.then_some(true),
+        stats: opts.stats.then_some(true),
+        ast: opts.ast.then_some(true),
+        repl: Repl {
+            prompt: opts.prompt.clone(),
+            continuation_prompt: opts.continuation_prompt.clone(),
+            banner: opts.banner.clone(),
+        },
+    }
+}
+
+fn show_config(path: Option<PathBuf>, settings: &Settings) -> Result<(), toml::ser::Error> {
+    match path {
+        Some(path) => println!("# merged from {} and flags", path.display()),
+        None => println!("# no {} found; defaults and flags only", config::FILE_NAME),
     }
+    print!("{}", toml::to_string(settings)?);
+    Ok(())
 }
 
-fn run(line: String) {
-    let mut scanner = Scanner::new(line);
+/// Runs `line`, returning the status `exit()` was called with, if it was.
+/// With `echo`, prints the value of a trailing expression statement, as the REPL does.
+fn run(line: &str, settings: &Settings, interpreter: &mut Interpreter, echo: bool) -> Option<i32> {
+    let scanner = Scanner::new(line);
     match scanner.scan() {
         Ok(tokens) => {
             // for tok in tokens.iter() {
@@ -31,37 +252,265 @@ fn run(line: String) {
             if tokens.len() > 1 {
                 match parse(&tokens) {
                     Ok(statements) => {
+                        let mut stats = Stats::default();
+                        let statements = optimize(statements, &mut stats);
+                        if settings.stats {
+                            print_stats(&stats);
+                        }
+                        if settings.ast {
+                            for stmt in statements.iter() {
+                                eprintln!("{}", stmt);
+                            }
+                        }
                         // println!("{:?}", statements);
-                        match interpret(statements) {
-                            Ok(()) => (),
-                            Err(runtime_error) => eprintln!("{:?}", runtime_error),
+                        match interpreter.interpret(&statements) {
+                            Ok(Some(value)) if echo => println!("{}", stringify(value)),
+                            Ok(_) => (),
+                            Err(Halt::Exit(code)) => return Some(code),
+                            Err(halt) => eprintln!("{}", halt),
                         }
                     }
-                    Err(parse_error) => eprintln!("{:?}", parse_error),
+                    Err(parse_error) => eprintln!("{}", parse_error),
                 }
             }
         }
-        Err(lexing_error) => eprintln!("{:?}", lexing_error),
+        Err(lexing_error) => eprintln!("{}", lexing_error),
+    }
+    None
+}
+
+fn print_version(opts: &Opts) {
+    let version = env!("CARGO_PKG_VERSION");
+    if !opts.verbose {
+        println!("rlox {}", version);
+        return;
+    }
+    let enabled: Vec<&str> = FEATURES
+        .iter()
+        .filter(|(_, enabled)| *enabled)
+        .map(|(name, _)| *name)
+        .collect();
+    let backends = ["tree-walking"];
+    if opts.json {
+        let list = |items: &[&str]| {
+            let quoted: Vec<String> = items.iter().map(|item| format!("\"{}\"", item)).collect();
+            format!("[{}]", quoted.join(", "))
+        };
+        println!("{{");
+        println!("  \"version\": \"{}\",", version);
+        println!("  \"features\": {},", list(&enabled));
+        println!("  \"backends\": {},", list(&backends));
+        println!("  \"extensions\": {},", list(EXTENSIONS));
+        println!("  \"options\": {}", list(OPTIONS));
+        println!("}}");
+    } else {
+        println!("rlox {}", version);
+        println!("features:   {}", enabled.join(", "));
+        println!("backends:   {}", backends.join(", "));
+        println!("extensions: {}", EXTENSIONS.join(", "));
+        println!("options:    {}", OPTIONS.join(", "));
     }
 }
 
-fn run_file(path: String) {
-    let content = std::fs::read_to_string(path).unwrap();
-    run(content);
+fn print_stats(stats: &Stats) {
+    eprintln!(
+        "optimizer: {} nodes before, {} after ({} peephole rewrites, {} constant folds)",
+        stats.nodes_before, stats.nodes_after, stats.peephole_rewrites, stats.constant_folds
+    );
 }
 
-fn run_prompt() {
+fn run_file(path: &str, opts: &Opts, settings: &Settings, options: Options) {
+    let content = match std::fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(error) => {
+            eprintln!("could not read {}: {}", path, error);
+            // EX_NOINPUT from sysexits.h
+            std::process::exit(66);
+        }
+    };
+    let mut interpreter = Interpreter::new(options);
+    run_prelude(opts, settings, &mut interpreter);
+    if let Some(code) = run(&content, settings, &mut interpreter, false) {
+        std::process::exit(code);
+    }
+}
+
+/// Fills in the variables available to REPL prompts and the banner
+struct PromptRenderer {
+    line_no: usize,
+    duration_of_last: Option<Duration>,
+}
+
+impl PromptRenderer {
+    fn render(&self, template: &str) -> String {
+        let duration_of_last = match self.duration_of_last {
+            Some(duration) => format!("{:?}", duration),
+            None => String::from("-"),
+        };
+        template
+            .replace("{line_no}", &self.line_no.to_string())
+            .replace("{duration_of_last}", &duration_of_last)
+            .replace("{version}", env!("CARGO_PKG_VERSION"))
+    }
+}
+
+/// Whether `source` only failed to scan or parse because it ended too early,
+/// meaning the REPL should keep reading lines before running it
+fn is_incomplete(source: &str) -> bool {
+    match Scanner::new(source).scan() {
+        Err(lexing_error) => lexing_error.is_unexpected_eof(),
+        Ok(tokens) => matches!(parse(&tokens), Err(parse_error) if parse_error.is_unexpected_eof()),
+    }
+}
+
+/// Ctrl-C cancels the line being typed, or interrupts the evaluation in progress
+#[derive(Clone, Default)]
+struct CtrlC {
+    /// Handed to the interpreter while it evaluates
+    interrupt: Arc<AtomicBool>,
+    /// Set when a line was cancelled while being typed
+    cancelled: Arc<AtomicBool>,
+    /// The prompt to show after cancelling a line, or `None` while evaluating
+    prompt: Arc<Mutex<Option<String>>>,
+}
+
+impl CtrlC {
+    #[cfg(unix)]
+    fn listen(&self) {
+        use signal_hook::consts::SIGINT;
+        use signal_hook::iterator::Signals;
+
+        let mut signals = match Signals::new([SIGINT]) {
+            Ok(signals) => signals,
+            Err(error) => {
+                eprintln!("Ctrl-C will exit the REPL: {}", error);
+                return;
+            }
+        };
+        let ctrl_c = self.clone();
+        std::thread::spawn(move || {
+            for _ in signals.forever() {
+                match &*ctrl_c.prompt.lock().unwrap() {
+                    // The terminal already dropped what was typed, so only the REPL's
+                    // unfinished statement is left to discard
+                    Some(prompt) => {
+                        ctrl_c.cancelled.store(true, Ordering::Relaxed);
+                        print!("\n{}", prompt);
+                        std::io::stdout().flush().unwrap();
+                    }
+                    None => ctrl_c.interrupt.store(true, Ordering::Relaxed),
+                }
+            }
+        });
+    }
+
+    #[cfg(not(unix))]
+    fn listen(&self) {}
+
+    fn set_prompt(&self, prompt: Option<String>) {
+        *self.prompt.lock().unwrap() = prompt;
+    }
+}
+
+/// Restores a session saved by an earlier REPL, unless it is the first one using `path`
+fn restore_session(path: &Path, interpreter: &mut Interpreter) {
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => return,
+        Err(error) => {
+            eprintln!("Cannot read session {}: {}", path.display(), error);
+            std::process::exit(1);
+        }
+    };
+    if let Err(error) = interpreter.restore(BufReader::new(file)) {
+        eprintln!("Cannot restore session {}: {}", path.display(), error);
+        std::process::exit(1);
+    }
+}
+
+/// Saves to a temporary file first, so a failed save keeps the previous session intact
+fn save_session(path: &Path, interpreter: &Interpreter) {
+    let temporary = path.with_extension("tmp");
+    let saved = File::create(&temporary)
+        .and_then(|file| interpreter.save(BufWriter::new(file)))
+        .and_then(|()| std::fs::rename(&temporary, path));
+    if let Err(error) = saved {
+        eprintln!("Cannot save session {}: {}", path.display(), error);
+    }
+}
+
+fn run_prompt(opts: &Opts, settings: &Settings, options: Options) {
     let stdin = std::io::stdin();
     let stdout = std::io::stdout();
+    let mut renderer = PromptRenderer {
+        line_no: 1,
+        duration_of_last: None,
+    };
+    if let Some(banner) = &settings.repl.banner {
+        println!("{}", renderer.render(banner));
+    }
+    let ctrl_c = CtrlC::default();
+    ctrl_c.listen();
+    let options = Options {
+        interrupt: Some(ctrl_c.interrupt.clone()),
+        ..options
+    };
+    let mut interpreter = Interpreter::new(options);
+    run_prelude(opts, settings, &mut interpreter);
+    if let Some(path) = &opts.session {
+        restore_session(path, &mut interpreter);
+    }
+    let mut input = String::new();
     loop {
-        print!("> ");
+        ctrl_c.set_prompt(Some(renderer.render(&settings.repl.prompt)));
+        let prompt = if input.is_empty() {
+            &settings.repl.prompt
+        } else {
+            &settings.repl.continuation_prompt
+        };
+        print!("{}", renderer.render(prompt));
         stdout.lock().flush().unwrap();
         let mut line = String::new();
-        match stdin.lock().read_line(&mut line) {
-            Ok(0) => break,
+        let read = stdin.lock().read_line(&mut line);
+        if ctrl_c.cancelled.swap(false, Ordering::Relaxed) {
+            input.clear();
+        }
+        match read {
+            // Ctrl-D only exits on an empty statement, otherwise it discards it
+            Ok(0) if input.is_empty() => break,
+            Ok(0) => {
+                println!();
+                input.clear();
+                continue;
+            }
             Ok(_) => (),
-            Err(error) => panic!("{}", error),
+            // The line is consumed even when it isn't valid UTF-8, so reading can go on
+            Err(error) if error.kind() == std::io::ErrorKind::InvalidData => {
+                eprintln!("{}", error);
+                input.clear();
+                continue;
+            }
+            Err(error) => {
+                eprintln!("Cannot read input: {}", error);
+                break;
+            }
+        }
+        input.push_str(&line);
+        if is_incomplete(&input) {
+            continue;
+        }
+        ctrl_c.set_prompt(None);
+        ctrl_c.interrupt.store(false, Ordering::Relaxed);
+        let started = Instant::now();
+        let code = run(&input, settings, &mut interpreter, true);
+        if let Some(path) = &opts.session {
+            save_session(path, &interpreter);
+        }
+        if let Some(code) = code {
+            std::process::exit(code);
         }
-        run(line);
+        renderer.duration_of_last = Some(started.elapsed());
+        renderer.line_no += 1;
+        input.clear();
     }
 }
@@ -0,0 +1,336 @@
+use std::convert::TryFrom;
+use std::io::{BufRead, Read, Write};
+use std::ops::RangeInclusive;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::callable::{CallError, Callable, NativeFunction};
+use crate::interpreter::{stringify, Deadline, Options, Output};
+use crate::value::{address, lox_hash, parse_number, MapKey, Value};
+
+mod json;
+mod lists;
+mod maps;
+mod math;
+#[cfg(feature = "net")]
+mod net;
+mod random;
+mod strings;
+
+/// The constants defined in every program's global environment
+pub fn constants() -> Vec<(&'static str, Value)> {
+    math::constants()
+}
+
+/// The builtins defined in every program's global environment, printing to `stdout` and
+/// waiting no later than `deadline`
+pub fn natives(options: &Options, stdout: &Output, deadline: &Deadline) -> Vec<Arc<dyn Callable>> {
+    let mut natives = vec![
+        NativeFunction::new(
+            "clock",
+            &[],
+            "Returns the seconds since the Unix epoch, with sub-second precision.",
+            clock,
+        ),
+        NativeFunction::new(
+            "error",
+            &["message"],
+            "Stops the program with a runtime error carrying message, like a failed assert.",
+            |mut arguments| Err(stringify(arguments.remove(0))),
+        ),
+        NativeFunction::new(
+            "hash",
+            &["value"],
+            "Returns an integer that is the same for values that are ==, within one run.",
+            |arguments| Ok(Value::Int(lox_hash(&arguments[0]) as i64)),
+        ),
+        NativeFunction::new(
+            "id",
+            &["function"],
+            "Returns a number identifying the function, telling apart ones of the same name.",
+            id,
+        ),
+        NativeFunction::new(
+            "len",
+            &["value"],
+            "Returns the number of characters in a string, or of elements in a list, map or range.",
+            len,
+        ),
+        NativeFunction::new(
+            "number",
+            &["value"],
+            "Parses a string like \"3.14\" into a number, or returns nil if it isn't one.",
+            number,
+        ),
+        NativeFunction::new(
+            "readAll",
+            &[],
+            "Reads the rest of standard input, returning \"\" once it has all been read.",
+            read_all,
+        ),
+        NativeFunction::new(
+            "readLine",
+            &[],
+            "Reads a line from standard input without its line break, or returns nil at the end of input.",
+            read_line,
+        ),
+        NativeFunction::new(
+            "str",
+            &["value"],
+            "Returns the value as print would show it.",
+            |mut arguments| Ok(Value::String(stringify(arguments.remove(0)).into())),
+        ),
+        NativeFunction::new(
+            "type",
+            &["value"],
+            "Returns the name of the value's type, like \"number\" or \"list\".",
+            type_of,
+        ),
+    ];
+    natives.extend(json::natives());
+    natives.extend(lists::natives());
+    natives.extend(maps::natives());
+    natives.extend(math::natives());
+    natives.extend(random::natives());
+    natives.extend(strings::natives());
+    #[cfg(feature = "net")]
+    natives.push(net::http_get(options.allow_net));
+    natives.push(help(stdout.clone()));
+    natives.push(sleep(options.interrupt.clone(), deadline.clone()));
+    let mut natives: Vec<Arc<dyn Callable>> = natives
+        .into_iter()
+        .map(|native| Arc::new(native) as Arc<dyn Callable>)
+        .collect();
+    natives.push(Arc::new(Exit));
+    natives.push(Arc::new(lists::Sort));
+    natives
+}
+
+/// `exit(code)`, which unwinds the interpreter instead of ending the process,
+/// so whoever runs the program decides what stopping means
+struct Exit;
+
+impl Callable for Exit {
+    fn name(&self) -> &str {
+        "exit"
+    }
+
+    fn arity(&self) -> RangeInclusive<usize> {
+        1..=1
+    }
+
+    fn call(&self, arguments: Vec<Value>) -> Result<Value, CallError> {
+        let code = integer_argument("exit", &arguments, 0).map_err(CallError::Error)?;
+        match i32::try_from(code) {
+            Ok(code) => Err(CallError::Exit(code)),
+            Err(_) => Err(CallError::Error(format!(
+                "exit() expects a status between {} and {}, got {}",
+                i32::MIN,
+                i32::MAX,
+                code
+            ))),
+        }
+    }
+
+    fn help(&self) -> String {
+        String::from("exit(code)\n    Stops the program with the given exit status.")
+    }
+}
+
+impl std::fmt::Debug for Exit {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "<native fn exit>")
+    }
+}
+
+/// The name of a value's type, as returned by `type()`
+pub fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Nil => "nil",
+        Value::Bool(_) => "bool",
+        Value::Function(_) => "function",
+        Value::Int(_) | Value::Number(_) => "number",
+        Value::List(_) => "list",
+        Value::Map(_) => "map",
+        Value::Range(_) => "range",
+        Value::String(_) => "string",
+    }
+}
+
+fn clock(_: Vec<Value>) -> Result<Value, String> {
+    match SystemTime::now().duration_since(UNIX_EPOCH) {
+        Ok(elapsed) => Ok(Value::Number(elapsed.as_secs_f64())),
+        Err(_) => Err(String::from(
+            "clock() failed: system time is before the Unix epoch",
+        )),
+    }
+}
+
+fn help(stdout: Output) -> NativeFunction {
+    NativeFunction::new(
+        "help",
+        &["value"],
+        "Prints how to call a function and what it does.",
+        move |mut arguments| {
+            let value = arguments.pop().unwrap_or_default();
+            let text = match &value {
+                Value::Function(function) => function.help(),
+                _ => {
+                    let name = type_name(&value);
+                    format!("{}: a {} value", stringify(value), name)
+                }
+            };
+            match writeln!(&stdout, "{}", text) {
+                Ok(()) => Ok(Value::Nil),
+                Err(error) => Err(format!("help() failed: {}", error)),
+            }
+        },
+    )
+}
+
+/// Only functions have an identity: everything else, lists and maps included, acts as if
+/// copied on assignment, as nothing changes them in place
+fn id(arguments: Vec<Value>) -> Result<Value, String> {
+    match &arguments[0] {
+        Value::Function(function) => Ok(Value::Int(address(function) as i64)),
+        other => Err(unexpected_argument(
+            "id",
+            0,
+            "a function, as other values are copied and have no identity",
+            other,
+        )),
+    }
+}
+
+fn len(arguments: Vec<Value>) -> Result<Value, String> {
+    let len = match &arguments[0] {
+        Value::List(elements) => elements.len() as u64,
+        Value::Map(map) => map.len() as u64,
+        Value::Range(range) => range.len(),
+        Value::String(s) => strings::graphemes(s).len() as u64,
+        other => {
+            return Err(format!(
+                "len() expects a string, list, map or range, got {}",
+                stringify(other.clone())
+            ))
+        }
+    };
+    Ok(Value::Int(len.min(i64::MAX as u64) as i64))
+}
+
+/// Reads back what `str()` produces for numbers, including `Infinity` and `NaN`
+fn number(arguments: Vec<Value>) -> Result<Value, String> {
+    match &arguments[0] {
+        Value::String(s) => Ok(parse_number(s).unwrap_or_default()),
+        number @ (Value::Int(_) | Value::Number(_)) => Ok(number.clone()),
+        other => Err(unexpected_argument("number", 0, "a string", other)),
+    }
+}
+
+fn read_all(_: Vec<Value>) -> Result<Value, String> {
+    let mut input = String::new();
+    match std::io::stdin().lock().read_to_string(&mut input) {
+        Ok(_) => Ok(Value::String(input.into())),
+        Err(error) => Err(format!("readAll() failed: {}", error)),
+    }
+}
+
+fn read_line(_: Vec<Value>) -> Result<Value, String> {
+    let mut line = String::new();
+    match std::io::stdin().lock().read_line(&mut line) {
+        Ok(0) => Ok(Value::Nil),
+        Ok(_) => {
+            if line.ends_with('\n') {
+                line.pop();
+                if line.ends_with('\r') {
+                    line.pop();
+                }
+            }
+            Ok(Value::String(line.into()))
+        }
+        Err(error) => Err(format!("readLine() failed: {}", error)),
+    }
+}
+
+fn type_of(arguments: Vec<Value>) -> Result<Value, String> {
+    Ok(Value::String(type_name(&arguments[0]).into()))
+}
+
+/// `sleep(ms)` wakes up early to stop with an error when `interrupt` is set, or to return
+/// at the run's `deadline`, which the interpreter then reports as the time limit
+fn sleep(interrupt: Option<Arc<AtomicBool>>, deadline: Deadline) -> NativeFunction {
+    NativeFunction::new(
+        "sleep",
+        &["ms"],
+        "Pauses the program for ms milliseconds.",
+        move |arguments| {
+            let ms = number_argument("sleep", &arguments, 0)?;
+            if !(0.0..=u64::MAX as f64).contains(&ms) {
+                return Err(format!(
+                    "sleep() expects a non-negative number of milliseconds, got {}",
+                    ms
+                ));
+            }
+            // Too far off to represent is as good as forever
+            let end = Instant::now().checked_add(Duration::from_secs_f64(ms / 1000.0));
+            loop {
+                if let Some(interrupt) = &interrupt {
+                    if interrupt.load(Ordering::Relaxed) {
+                        return Err(String::from("Interrupted"));
+                    }
+                }
+                let wake = end.into_iter().chain(deadline.get()).min();
+                let now = Instant::now();
+                let pause = match wake {
+                    Some(wake) if now >= wake => return Ok(Value::Nil),
+                    Some(wake) => wake - now,
+                    None => Duration::MAX,
+                };
+                std::thread::sleep(pause.min(Duration::from_millis(10)));
+            }
+        },
+    )
+}
+
+fn string_argument<'a>(
+    function: &str,
+    arguments: &'a [Value],
+    index: usize,
+) -> Result<&'a str, String> {
+    match &arguments[index] {
+        Value::String(s) => Ok(s),
+        other => Err(unexpected_argument(function, index, "a string", other)),
+    }
+}
+
+fn number_argument(function: &str, arguments: &[Value], index: usize) -> Result<f64, String> {
+    match &arguments[index] {
+        Value::Int(n) => Ok(*n as f64),
+        Value::Number(n) => Ok(*n),
+        other => Err(unexpected_argument(function, index, "a number", other)),
+    }
+}
+
+/// Integral floats like `2.0` are accepted too
+fn integer_argument(function: &str, arguments: &[Value], index: usize) -> Result<i64, String> {
+    match MapKey::from_value(&arguments[index]) {
+        Some(MapKey::Int(n)) => Ok(n),
+        _ => Err(unexpected_argument(
+            function,
+            index,
+            "an integer",
+            &arguments[index],
+        )),
+    }
+}
+
+fn unexpected_argument(function: &str, index: usize, expected: &str, got: &Value) -> String {
+    format!(
+        "{}() expects argument {} to be {}, got {}",
+        function,
+        index + 1,
+        expected,
+        stringify(got.clone())
+    )
+}
@@ -0,0 +1,373 @@
+use std::collections::BTreeMap;
+use std::iter::Peekable;
+use std::str::CharIndices;
+use std::sync::Arc;
+
+use crate::callable::NativeFunction;
+use crate::interpreter::{is_truthy, stringify};
+use crate::parsing::MAX_NESTING;
+use crate::value::{MapKey, Value};
+
+use super::string_argument;
+
+pub(super) fn natives() -> Vec<NativeFunction> {
+    vec![
+        NativeFunction::new(
+            "jsonParse",
+            &["text"],
+            "Parses JSON into nil, booleans, numbers, strings, lists and maps.",
+            |arguments| {
+                let text = string_argument("jsonParse", &arguments, 0)?;
+                parse(text).map_err(|error| format!("jsonParse() failed: {}", error))
+            },
+        ),
+        NativeFunction::new(
+            "jsonStringify",
+            &["value", "pretty?"],
+            "Returns value as JSON, indented over several lines if pretty is true.",
+            |mut arguments| {
+                let pretty = is_truthy(arguments.pop().unwrap_or_default());
+                let mut json = String::new();
+                let indent = if pretty { Some(0) } else { None };
+                write_value(&arguments[0], indent, &mut json)
+                    .map_err(|error| format!("jsonStringify() failed: {}", error))?;
+                Ok(Value::String(json.into()))
+            },
+        ),
+    ]
+}
+
+fn parse(text: &str) -> Result<Value, String> {
+    let mut parser = Parser {
+        text,
+        chars: text.char_indices().peekable(),
+        depth: 0,
+    };
+    let value = parser.value()?;
+    parser.skip_whitespace();
+    match parser.chars.next() {
+        None => Ok(value),
+        Some((at, c)) => Err(format!("unexpected '{}' after the value at byte {}", c, at)),
+    }
+}
+
+/// A recursive descent parser for RFC 8259 JSON, nesting at most `MAX_NESTING` levels
+struct Parser<'a> {
+    text: &'a str,
+    chars: Peekable<CharIndices<'a>>,
+    depth: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn value(&mut self) -> Result<Value, String> {
+        self.skip_whitespace();
+        match self.chars.peek().copied() {
+            Some((_, '{')) => self.nested(Parser::object),
+            Some((_, '[')) => self.nested(Parser::array),
+            Some((_, '"')) => Ok(Value::String(self.string()?.into())),
+            Some((_, '-' | '0'..='9')) => self.number(),
+            Some((at, c)) if c.is_ascii_alphabetic() => {
+                let word: String = self.text[at..]
+                    .chars()
+                    .take_while(char::is_ascii_alphabetic)
+                    .collect();
+                let value = match word.as_str() {
+                    "true" => Value::Bool(true),
+                    "false" => Value::Bool(false),
+                    "null" => Value::Nil,
+                    _ => return Err(format!("unexpected '{}' at byte {}", word, at)),
+                };
+                self.chars.nth(word.len() - 1);
+                Ok(value)
+            }
+            Some((at, c)) => Err(format!("unexpected '{}' at byte {}", c, at)),
+            None => Err(String::from("unexpected end of input")),
+        }
+    }
+
+    fn nested(
+        &mut self,
+        rule: fn(&mut Parser<'a>) -> Result<Value, String>,
+    ) -> Result<Value, String> {
+        if self.depth == MAX_NESTING {
+            return Err(format!("nested more than {} levels deep", MAX_NESTING));
+        }
+        self.depth += 1;
+        let value = rule(self);
+        self.depth -= 1;
+        value
+    }
+
+    fn object(&mut self) -> Result<Value, String> {
+        self.expect('{')?;
+        let mut map = BTreeMap::new();
+        self.skip_whitespace();
+        if self.eat('}') {
+            return Ok(Value::Map(Arc::new(map)));
+        }
+        loop {
+            self.skip_whitespace();
+            let key = self.string()?;
+            self.skip_whitespace();
+            self.expect(':')?;
+            let value = self.value()?;
+            map.insert(MapKey::String(key), value);
+            self.skip_whitespace();
+            if self.eat('}') {
+                return Ok(Value::Map(Arc::new(map)));
+            }
+            self.expect(',')?;
+        }
+    }
+
+    fn array(&mut self) -> Result<Value, String> {
+        self.expect('[')?;
+        let mut elements = vec![];
+        self.skip_whitespace();
+        if self.eat(']') {
+            return Ok(Value::List(Arc::new(elements)));
+        }
+        loop {
+            elements.push(self.value()?);
+            self.skip_whitespace();
+            if self.eat(']') {
+                return Ok(Value::List(Arc::new(elements)));
+            }
+            self.expect(',')?;
+        }
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        self.expect('"')?;
+        let mut string = String::new();
+        loop {
+            match self.chars.next() {
+                Some((_, '"')) => return Ok(string),
+                Some((_, '\\')) => string.push(self.escape()?),
+                Some((at, c)) if c < ' ' => {
+                    return Err(format!("unescaped control character at byte {}", at))
+                }
+                Some((_, c)) => string.push(c),
+                None => return Err(String::from("unterminated string")),
+            }
+        }
+    }
+
+    fn escape(&mut self) -> Result<char, String> {
+        match self.chars.next() {
+            Some((_, '"')) => Ok('"'),
+            Some((_, '\\')) => Ok('\\'),
+            Some((_, '/')) => Ok('/'),
+            Some((_, 'b')) => Ok('\u{8}'),
+            Some((_, 'f')) => Ok('\u{c}'),
+            Some((_, 'n')) => Ok('\n'),
+            Some((_, 'r')) => Ok('\r'),
+            Some((_, 't')) => Ok('\t'),
+            Some((at, 'u')) => {
+                let unit = self.hex4()?;
+                let code = if (0xd800..0xdc00).contains(&unit) {
+                    // A high surrogate must be followed by an escaped low one
+                    if !(self.eat('\\') && self.eat('u')) {
+                        return Err(format!("unpaired surrogate at byte {}", at));
+                    }
+                    let low = self.hex4()?;
+                    if !(0xdc00..0xe000).contains(&low) {
+                        return Err(format!("unpaired surrogate at byte {}", at));
+                    }
+                    0x10000 + ((unit - 0xd800) << 10) + (low - 0xdc00)
+                } else {
+                    unit
+                };
+                char::from_u32(code).ok_or_else(|| format!("unpaired surrogate at byte {}", at))
+            }
+            Some((at, c)) => Err(format!("invalid escape '\\{}' at byte {}", c, at)),
+            None => Err(String::from("unterminated string")),
+        }
+    }
+
+    fn hex4(&mut self) -> Result<u32, String> {
+        let mut unit = 0;
+        for _ in 0..4 {
+            match self.chars.next() {
+                Some((_, c)) if c.is_ascii_hexdigit() => unit = unit * 16 + c.to_digit(16).unwrap(),
+                Some((at, c)) => return Err(format!("invalid hex digit '{}' at byte {}", c, at)),
+                None => return Err(String::from("unterminated string")),
+            }
+        }
+        Ok(unit)
+    }
+
+    /// Integers become Lox integers when they fit in one, everything else a float
+    fn number(&mut self) -> Result<Value, String> {
+        let start = self.chars.peek().map_or(self.text.len(), |(at, _)| *at);
+        self.eat('-');
+        if !self.eat('0') && self.digits() == 0 {
+            return Err(format!("expected a digit at byte {}", self.position()));
+        }
+        let mut is_integer = true;
+        if self.eat('.') {
+            is_integer = false;
+            if self.digits() == 0 {
+                return Err(format!("expected a digit at byte {}", self.position()));
+            }
+        }
+        if self.eat('e') || self.eat('E') {
+            is_integer = false;
+            if !self.eat('+') {
+                self.eat('-');
+            }
+            if self.digits() == 0 {
+                return Err(format!("expected a digit at byte {}", self.position()));
+            }
+        }
+        let literal = &self.text[start..self.position()];
+        match literal.parse::<i64>() {
+            Ok(int) if is_integer => Ok(Value::Int(int)),
+            _ => match literal.parse::<f64>() {
+                Ok(number) => Ok(Value::Number(number)),
+                Err(_) => Err(format!("invalid number '{}' at byte {}", literal, start)),
+            },
+        }
+    }
+
+    fn digits(&mut self) -> usize {
+        let mut count = 0;
+        while let Some((_, '0'..='9')) = self.chars.peek() {
+            self.chars.next();
+            count += 1;
+        }
+        count
+    }
+
+    fn position(&mut self) -> usize {
+        self.chars.peek().map_or(self.text.len(), |(at, _)| *at)
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some((_, ' ' | '\t' | '\n' | '\r')) = self.chars.peek() {
+            self.chars.next();
+        }
+    }
+
+    fn eat(&mut self, expected: char) -> bool {
+        match self.chars.peek() {
+            Some((_, c)) if *c == expected => {
+                self.chars.next();
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), String> {
+        match self.chars.next() {
+            Some((_, c)) if c == expected => Ok(()),
+            Some((at, c)) => Err(format!(
+                "expected '{}' but got '{}' at byte {}",
+                expected, c, at
+            )),
+            None => Err(format!("expected '{}' but got end of input", expected)),
+        }
+    }
+}
+
+/// Writes `value` as JSON, on several lines indented by `indent` levels if it's `Some`.
+///
+/// Numeric map keys are written as strings, since JSON keys can't be anything else.
+fn write_value(value: &Value, indent: Option<usize>, json: &mut String) -> Result<(), String> {
+    // An explicit stack rather than recursion, so deep nesting can't overflow
+    let mut pending = vec![Piece::Value(value, indent)];
+    while let Some(piece) = pending.pop() {
+        let (value, indent) = match piece {
+            Piece::Text(text) => {
+                json.push_str(text);
+                continue;
+            }
+            Piece::Newline(indent) => {
+                write_newline(indent, json);
+                continue;
+            }
+            Piece::Key(key) => {
+                write_string(&stringify(key.to_value()), json);
+                continue;
+            }
+            Piece::Value(value, indent) => (value, indent),
+        };
+        let inner = indent.map(|indent| indent + 1);
+        match value {
+            Value::Nil => json.push_str("null"),
+            Value::Bool(b) => json.push_str(&b.to_string()),
+            Value::Int(n) => json.push_str(&n.to_string()),
+            Value::Number(n) if n.is_finite() => json.push_str(&format!("{:?}", n)),
+            Value::String(s) => write_string(s, json),
+            Value::List(elements) => {
+                json.push('[');
+                pending.push(Piece::Text("]"));
+                if !elements.is_empty() {
+                    pending.push(Piece::Newline(indent));
+                }
+                for (i, element) in elements.iter().enumerate().rev() {
+                    pending.push(Piece::Value(element, inner));
+                    pending.push(Piece::Newline(inner));
+                    if i > 0 {
+                        pending.push(Piece::Text(","));
+                    }
+                }
+            }
+            Value::Map(map) => {
+                json.push('{');
+                pending.push(Piece::Text("}"));
+                if !map.is_empty() {
+                    pending.push(Piece::Newline(indent));
+                }
+                for (i, (key, value)) in map.iter().enumerate().rev() {
+                    pending.push(Piece::Value(value, inner));
+                    pending.push(Piece::Text(if indent.is_some() { ": " } else { ":" }));
+                    pending.push(Piece::Key(key));
+                    pending.push(Piece::Newline(inner));
+                    if i > 0 {
+                        pending.push(Piece::Text(","));
+                    }
+                }
+            }
+            other => {
+                return Err(format!(
+                    "{} can't be represented in JSON",
+                    stringify(other.clone())
+                ))
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Something `write_value` has left to write, with the indentation it's written at
+enum Piece<'a> {
+    Value(&'a Value, Option<usize>),
+    Key(&'a MapKey),
+    Newline(Option<usize>),
+    Text(&'static str),
+}
+
+fn write_newline(indent: Option<usize>, json: &mut String) {
+    if let Some(indent) = indent {
+        json.push('\n');
+        json.push_str(&"  ".repeat(indent));
+    }
+}
+
+fn write_string(s: &str, json: &mut String) {
+    json.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\r' => json.push_str("\\r"),
+            '\t' => json.push_str("\\t"),
+            c if c < ' ' => json.push_str(&format!("\\u{:04x}", c as u32)),
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+}
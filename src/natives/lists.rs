@@ -0,0 +1,158 @@
+use std::cmp::Ordering;
+use std::ops::RangeInclusive;
+use std::sync::Arc;
+
+use crate::callable::{check_arity, CallError, Callable, NativeFunction};
+use crate::interpreter::stringify;
+use crate::value::{as_number, compare_numbers, lox_cmp, Number, Value};
+
+use super::unexpected_argument;
+
+/// List functions. Lists are values, so `push` and `pop` return new lists, copying the
+/// elements only when the list is shared
+pub(super) fn natives() -> Vec<NativeFunction> {
+    vec![
+        NativeFunction::new(
+            "pop",
+            &["list"],
+            "Returns a copy of list without its last element.",
+            pop,
+        ),
+        NativeFunction::new(
+            "push",
+            &["list", "value"],
+            "Returns a copy of list with value added at the end.",
+            push,
+        ),
+    ]
+}
+
+fn list_argument(
+    function: &str,
+    arguments: &mut [Value],
+    index: usize,
+) -> Result<Arc<Vec<Value>>, String> {
+    match &mut arguments[index] {
+        Value::List(elements) => Ok(std::mem::take(elements)),
+        other => Err(unexpected_argument(function, index, "a list", other)),
+    }
+}
+
+fn pop(mut arguments: Vec<Value>) -> Result<Value, String> {
+    let mut elements = list_argument("pop", &mut arguments, 0)?;
+    if elements.is_empty() {
+        return Err(String::from("pop() expects a non-empty list"));
+    }
+    Arc::make_mut(&mut elements).pop();
+    Ok(Value::List(elements))
+}
+
+fn push(mut arguments: Vec<Value>) -> Result<Value, String> {
+    let mut elements = list_argument("push", &mut arguments, 0)?;
+    let value = arguments.pop().unwrap_or_default();
+    Arc::make_mut(&mut elements).push(value);
+    Ok(Value::List(elements))
+}
+
+/// `sort(list, comparator?)`, a `Callable` of its own so errors and `exit()` from the
+/// comparator unwind through it
+pub(super) struct Sort;
+
+impl Callable for Sort {
+    fn name(&self) -> &str {
+        "sort"
+    }
+
+    fn arity(&self) -> RangeInclusive<usize> {
+        1..=2
+    }
+
+    fn call(&self, mut arguments: Vec<Value>) -> Result<Value, CallError> {
+        let comparator = if arguments.len() == 2 {
+            arguments.pop().unwrap_or_default()
+        } else {
+            Value::Nil
+        };
+        let mut list = arguments.pop().unwrap_or_default();
+        // Taken rather than cloned, so a list nothing else shares is sorted in place
+        let mut elements = match &mut list {
+            Value::List(elements) => std::mem::take(elements),
+            other => {
+                return Err(CallError::Error(unexpected_argument(
+                    "sort", 0, "a list", other,
+                )))
+            }
+        };
+        match &comparator {
+            Value::Nil => Arc::make_mut(&mut elements).sort_by(lox_cmp),
+            Value::Function(function) => {
+                check_arity(function.as_ref(), 2).map_err(CallError::Error)?;
+                let unsorted = Arc::unwrap_or_clone(elements);
+                elements = Arc::new(merge_sort(unsorted, &mut |lhs, rhs| {
+                    let order = function.call(vec![lhs.clone(), rhs.clone()])?;
+                    ordering(&order).ok_or_else(|| {
+                        CallError::Error(format!(
+                            "sort() expects the comparator to return a number, got {}",
+                            stringify(order)
+                        ))
+                    })
+                })?)
+            }
+            other => {
+                return Err(CallError::Error(unexpected_argument(
+                    "sort",
+                    1,
+                    "a function",
+                    other,
+                )))
+            }
+        }
+        Ok(Value::List(elements))
+    }
+
+    fn help(&self) -> String {
+        String::from(
+            "sort(list, comparator?)\n    Returns a sorted copy of list. comparator(a, b), if \
+             given, returns a negative number if a goes first, positive if b does, or 0.",
+        )
+    }
+}
+
+impl std::fmt::Debug for Sort {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "<native fn sort>")
+    }
+}
+
+/// The sign of a comparator's result, or `None` if it isn't a number
+fn ordering(order: &Value) -> Option<Ordering> {
+    let order = as_number(order)?;
+    compare_numbers(order, Number::Int(0))
+}
+
+/// A stable merge sort that stops at the first error, and doesn't mind a comparator
+/// that contradicts itself
+fn merge_sort<F>(mut elements: Vec<Value>, compare: &mut F) -> Result<Vec<Value>, CallError>
+where
+    F: FnMut(&Value, &Value) -> Result<Ordering, CallError>,
+{
+    if elements.len() < 2 {
+        return Ok(elements);
+    }
+    let right = elements.split_off(elements.len() / 2);
+    let left = merge_sort(elements, compare)?;
+    let right = merge_sort(right, compare)?;
+    let mut merged = Vec::with_capacity(left.len() + right.len());
+    let mut left = left.into_iter().peekable();
+    let mut right = right.into_iter().peekable();
+    while let (Some(lhs), Some(rhs)) = (left.peek(), right.peek()) {
+        if compare(rhs, lhs)? == Ordering::Less {
+            merged.extend(right.next());
+        } else {
+            merged.extend(left.next());
+        }
+    }
+    merged.extend(left);
+    merged.extend(right);
+    Ok(merged)
+}
@@ -0,0 +1,97 @@
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use crate::callable::NativeFunction;
+use crate::value::{MapKey, Value};
+
+use super::unexpected_argument;
+
+/// Map functions. Maps are values, so `remove` and `merge` return new maps, copying the
+/// entries only when the map is shared
+pub(super) fn natives() -> Vec<NativeFunction> {
+    vec![
+        NativeFunction::new(
+            "has",
+            &["map", "key"],
+            "Returns whether map has an entry for key, even one set to nil.",
+            has,
+        ),
+        NativeFunction::new(
+            "keys",
+            &["map"],
+            "Returns a list of the map's keys, in order.",
+            keys,
+        ),
+        NativeFunction::new(
+            "merge",
+            &["map", "other"],
+            "Returns a map with the entries of both, preferring other's where keys clash.",
+            merge,
+        ),
+        NativeFunction::new(
+            "remove",
+            &["map", "key"],
+            "Returns a copy of map without the entry for key.",
+            remove,
+        ),
+        NativeFunction::new(
+            "values",
+            &["map"],
+            "Returns a list of the map's values, in the order of their keys.",
+            values,
+        ),
+    ]
+}
+
+type Map = BTreeMap<MapKey, Value>;
+
+fn map_argument(function: &str, arguments: &mut [Value], index: usize) -> Result<Arc<Map>, String> {
+    match &mut arguments[index] {
+        Value::Map(map) => Ok(std::mem::take(map)),
+        other => Err(unexpected_argument(function, index, "a map", other)),
+    }
+}
+
+fn key_argument(function: &str, arguments: &[Value], index: usize) -> Result<MapKey, String> {
+    MapKey::from_value(&arguments[index]).ok_or_else(|| {
+        unexpected_argument(
+            function,
+            index,
+            "a string or number (and not NaN)",
+            &arguments[index],
+        )
+    })
+}
+
+fn has(mut arguments: Vec<Value>) -> Result<Value, String> {
+    let map = map_argument("has", &mut arguments, 0)?;
+    let key = key_argument("has", &arguments, 1)?;
+    Ok(Value::Bool(map.contains_key(&key)))
+}
+
+fn keys(mut arguments: Vec<Value>) -> Result<Value, String> {
+    let map = map_argument("keys", &mut arguments, 0)?;
+    let keys = map.keys().map(MapKey::to_value).collect();
+    Ok(Value::List(Arc::new(keys)))
+}
+
+fn merge(mut arguments: Vec<Value>) -> Result<Value, String> {
+    let mut map = map_argument("merge", &mut arguments, 0)?;
+    let other = map_argument("merge", &mut arguments, 1)?;
+    Arc::make_mut(&mut map).extend(Arc::unwrap_or_clone(other));
+    Ok(Value::Map(map))
+}
+
+fn remove(mut arguments: Vec<Value>) -> Result<Value, String> {
+    let mut map = map_argument("remove", &mut arguments, 0)?;
+    let key = key_argument("remove", &arguments, 1)?;
+    if map.contains_key(&key) {
+        Arc::make_mut(&mut map).remove(&key);
+    }
+    Ok(Value::Map(map))
+}
+
+fn values(mut arguments: Vec<Value>) -> Result<Value, String> {
+    let map = map_argument("values", &mut arguments, 0)?;
+    Ok(Value::List(Arc::new(map.values().cloned().collect())))
+}
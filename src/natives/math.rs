@@ -0,0 +1,133 @@
+use std::cmp::Ordering;
+use std::convert::TryFrom;
+
+use crate::callable::NativeFunction;
+use crate::value::{as_number, compare_numbers, Value};
+
+use super::{number_argument, unexpected_argument};
+
+pub(super) fn constants() -> Vec<(&'static str, Value)> {
+    vec![
+        ("E", Value::Number(std::f64::consts::E)),
+        ("PI", Value::Number(std::f64::consts::PI)),
+    ]
+}
+
+pub(super) fn natives() -> Vec<NativeFunction> {
+    vec![
+        NativeFunction::new("abs", &["x"], "Returns the absolute value of x.", abs),
+        NativeFunction::new(
+            "ceil",
+            &["x"],
+            "Returns the smallest integer not less than x.",
+            |arguments| rounding("ceil", &arguments, f64::ceil),
+        ),
+        NativeFunction::new(
+            "cos",
+            &["x"],
+            "Returns the cosine of x, in radians.",
+            |arguments| float("cos", &arguments, f64::cos),
+        ),
+        NativeFunction::new(
+            "floor",
+            &["x"],
+            "Returns the largest integer not greater than x.",
+            |arguments| rounding("floor", &arguments, f64::floor),
+        ),
+        NativeFunction::new(
+            "log",
+            &["x"],
+            "Returns the natural logarithm of x.",
+            |arguments| float("log", &arguments, f64::ln),
+        ),
+        NativeFunction::new(
+            "max",
+            &["a", "b"],
+            "Returns the larger of two numbers, or NaN if either is NaN.",
+            |arguments| extreme("max", &arguments, Ordering::Greater),
+        ),
+        NativeFunction::new(
+            "min",
+            &["a", "b"],
+            "Returns the smaller of two numbers, or NaN if either is NaN.",
+            |arguments| extreme("min", &arguments, Ordering::Less),
+        ),
+        NativeFunction::new(
+            "pow",
+            &["x", "y"],
+            "Returns x raised to the power y, like x ** y.",
+            pow,
+        ),
+        NativeFunction::new(
+            "round",
+            &["x"],
+            "Returns x rounded to the nearest integer, with halves rounded away from zero.",
+            |arguments| rounding("round", &arguments, f64::round),
+        ),
+        NativeFunction::new(
+            "sin",
+            &["x"],
+            "Returns the sine of x, in radians.",
+            |arguments| float("sin", &arguments, f64::sin),
+        ),
+        NativeFunction::new(
+            "sqrt",
+            &["x"],
+            "Returns the square root of x.",
+            |arguments| float("sqrt", &arguments, f64::sqrt),
+        ),
+    ]
+}
+
+fn float(name: &str, arguments: &[Value], f: fn(f64) -> f64) -> Result<Value, String> {
+    let x = number_argument(name, arguments, 0)?;
+    Ok(Value::Number(f(x)))
+}
+
+/// Integers are returned as they are; floats are rounded with `f`, becoming integers
+/// when the result fits in one
+fn rounding(name: &str, arguments: &[Value], f: fn(f64) -> f64) -> Result<Value, String> {
+    if let Value::Int(n) = arguments[0] {
+        return Ok(Value::Int(n));
+    }
+    let x = f(number_argument(name, arguments, 0)?);
+    if x >= i64::MIN as f64 && x < i64::MAX as f64 {
+        Ok(Value::Int(x as i64))
+    } else {
+        Ok(Value::Number(x))
+    }
+}
+
+fn abs(arguments: Vec<Value>) -> Result<Value, String> {
+    match arguments[0] {
+        Value::Int(n) if n != i64::MIN => Ok(Value::Int(n.abs())),
+        _ => float("abs", &arguments, f64::abs),
+    }
+}
+
+/// Returns whichever argument compares as `wanted` against the other, keeping integers intact
+fn extreme(name: &str, arguments: &[Value], wanted: Ordering) -> Result<Value, String> {
+    let mut numbers = vec![];
+    for (index, argument) in arguments.iter().enumerate() {
+        match as_number(argument) {
+            Some(number) => numbers.push(number),
+            None => return Err(unexpected_argument(name, index, "a number", argument)),
+        }
+    }
+    match compare_numbers(numbers[0], numbers[1]) {
+        None => Ok(Value::Number(f64::NAN)),
+        Some(ordering) if ordering == wanted => Ok(arguments[0].clone()),
+        Some(_) => Ok(arguments[1].clone()),
+    }
+}
+
+fn pow(arguments: Vec<Value>) -> Result<Value, String> {
+    if let (Value::Int(x), Value::Int(y)) = (&arguments[0], &arguments[1]) {
+        if let Some(result) = u32::try_from(*y).ok().and_then(|y| x.checked_pow(y)) {
+            return Ok(Value::Int(result));
+        }
+    }
+    let x = number_argument("pow", &arguments, 0)?;
+    let y = number_argument("pow", &arguments, 1)?;
+    Ok(Value::Number(x.powf(y)))
+}
@@ -0,0 +1,37 @@
+use std::time::Duration;
+
+use crate::callable::NativeFunction;
+use crate::value::Value;
+
+use super::string_argument;
+
+/// `httpGet(url)`, which fails unless `allowed`, so scripts only reach the network
+/// when whoever runs them says so
+pub(super) fn http_get(allowed: bool) -> NativeFunction {
+    let agent = ureq::AgentBuilder::new()
+        .timeout(Duration::from_secs(30))
+        .build();
+    NativeFunction::new(
+        "httpGet",
+        &["url"],
+        "Returns the body of the response to a GET request for url. Needs --allow-net.",
+        move |arguments| {
+            let url = string_argument("httpGet", &arguments, 0)?;
+            if !allowed {
+                return Err(String::from(
+                    "httpGet() is disabled; run with --allow-net to enable it",
+                ));
+            }
+            match agent.get(url).call() {
+                Ok(response) => match response.into_string() {
+                    Ok(body) => Ok(Value::String(body.into())),
+                    Err(error) => Err(format!("httpGet() failed to read {}: {}", url, error)),
+                },
+                Err(ureq::Error::Status(status, _)) => {
+                    Err(format!("httpGet() got status {} from {}", status, url))
+                }
+                Err(error) => Err(format!("httpGet() failed: {}", error)),
+            }
+        },
+    )
+}
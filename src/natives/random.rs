@@ -0,0 +1,97 @@
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::callable::NativeFunction;
+use crate::value::Value;
+
+use super::integer_argument;
+
+/// SplitMix64: tiny, fast and good enough for scripts, but not for cryptography
+struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    fn from_clock() -> Rng {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_nanos() as u64);
+        Rng { state: nanos }
+    }
+
+    fn next(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform in `[0, 1)`, using the top 53 bits so every value is exact
+    fn next_float(&mut self) -> f64 {
+        (self.next() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// Uniform in `[0, bound]`, rejecting draws that would favor small values
+    fn next_up_to(&mut self, bound: u64) -> u64 {
+        if bound == u64::MAX {
+            return self.next();
+        }
+        let range = bound + 1;
+        let limit = u64::MAX - u64::MAX % range;
+        loop {
+            let draw = self.next();
+            if draw < limit {
+                return draw % range;
+            }
+        }
+    }
+}
+
+/// The three functions share one generator, seeded from the clock until `seed()` is called
+pub(super) fn natives() -> Vec<NativeFunction> {
+    let rng = Arc::new(Mutex::new(Rng::from_clock()));
+    let random = Arc::clone(&rng);
+    let random_int = Arc::clone(&rng);
+    vec![
+        NativeFunction::new(
+            "random",
+            &[],
+            "Returns a random number from 0 up to, but not including, 1.",
+            move |_| {
+                let x = random.lock().unwrap().next_float();
+                Ok(Value::Number(x))
+            },
+        ),
+        NativeFunction::new(
+            "randomInt",
+            &["lo", "hi"],
+            "Returns a random integer from lo to hi, both included.",
+            move |arguments| {
+                let lo = integer_argument("randomInt", &arguments, 0)?;
+                let hi = integer_argument("randomInt", &arguments, 1)?;
+                if lo > hi {
+                    return Err(format!(
+                        "randomInt() expects lo <= hi, got {} and {}",
+                        lo, hi
+                    ));
+                }
+                let offset = random_int
+                    .lock()
+                    .unwrap()
+                    .next_up_to(hi.wrapping_sub(lo) as u64);
+                Ok(Value::Int(lo.wrapping_add(offset as i64)))
+            },
+        ),
+        NativeFunction::new(
+            "seed",
+            &["n"],
+            "Restarts random() and randomInt() from seed n, making them repeatable.",
+            move |arguments| {
+                let n = integer_argument("seed", &arguments, 0)?;
+                *rng.lock().unwrap() = Rng { state: n as u64 };
+                Ok(Value::Nil)
+            },
+        ),
+    ]
+}
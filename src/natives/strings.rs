@@ -0,0 +1,254 @@
+use std::iter::Peekable;
+use std::str::Chars;
+use std::sync::Arc;
+
+use crate::callable::NativeFunction;
+use crate::interpreter::stringify;
+use crate::lexing::Scanner;
+use crate::value::Value;
+
+use super::{integer_argument, string_argument, unexpected_argument};
+
+/// String functions, which count and index grapheme clusters the way the lexer reads source
+pub(super) fn natives() -> Vec<NativeFunction> {
+    vec![
+        NativeFunction::new(
+            "contains",
+            &["s", "part"],
+            "Returns whether part occurs in s.",
+            contains,
+        ),
+        NativeFunction::new(
+            "format",
+            &["template", "values..."],
+            "Replaces each {} in template with the next value, or {i} with value i. \
+             {:.2} rounds to 2 decimals, {:8} pads to 8 characters and {:<8}, {:^8} \
+             or {:>8} align the padding; {{ and }} are literal braces.",
+            format,
+        ),
+        NativeFunction::new(
+            "indexOf",
+            &["s", "part"],
+            "Returns the position of the first part in s, or -1 if there is none.",
+            index_of,
+        ),
+        NativeFunction::new("lower", &["s"], "Returns s in lowercase.", |arguments| {
+            map_string("lower", &arguments, str::to_lowercase)
+        }),
+        NativeFunction::new(
+            "split",
+            &["s", "separator"],
+            "Splits s at every separator, or into single characters if separator is \"\".",
+            split,
+        ),
+        NativeFunction::new(
+            "substr",
+            &["s", "start", "length"],
+            "Returns up to length characters of s, starting at position start.",
+            substr,
+        ),
+        NativeFunction::new(
+            "trim",
+            &["s"],
+            "Returns s without leading and trailing whitespace.",
+            |arguments| map_string("trim", &arguments, |s| String::from(s.trim())),
+        ),
+        NativeFunction::new("upper", &["s"], "Returns s in uppercase.", |arguments| {
+            map_string("upper", &arguments, str::to_uppercase)
+        }),
+    ]
+}
+
+/// Splits `s` into grapheme clusters
+pub(super) fn graphemes(s: &str) -> Vec<&str> {
+    Scanner::graphemes(s).collect()
+}
+
+/// Where `needle` first starts in `haystack` at or after `from`, matching whole graphemes only
+fn find(haystack: &[&str], needle: &[&str], from: usize) -> Option<usize> {
+    if needle.is_empty() {
+        return Some(from).filter(|from| *from <= haystack.len());
+    }
+    (from..=haystack.len().saturating_sub(needle.len()))
+        .find(|start| haystack[*start..].starts_with(needle))
+}
+
+fn string(s: String) -> Value {
+    Value::String(s.into())
+}
+
+fn map_string(name: &str, arguments: &[Value], f: fn(&str) -> String) -> Result<Value, String> {
+    Ok(string(f(string_argument(name, arguments, 0)?)))
+}
+
+fn contains(arguments: Vec<Value>) -> Result<Value, String> {
+    let s = graphemes(string_argument("contains", &arguments, 0)?);
+    let part = graphemes(string_argument("contains", &arguments, 1)?);
+    Ok(Value::Bool(find(&s, &part, 0).is_some()))
+}
+
+fn index_of(arguments: Vec<Value>) -> Result<Value, String> {
+    let s = graphemes(string_argument("indexOf", &arguments, 0)?);
+    let part = graphemes(string_argument("indexOf", &arguments, 1)?);
+    let index = find(&s, &part, 0).map_or(-1, |index| index as i64);
+    Ok(Value::Int(index))
+}
+
+fn split(arguments: Vec<Value>) -> Result<Value, String> {
+    let s = graphemes(string_argument("split", &arguments, 0)?);
+    let separator = graphemes(string_argument("split", &arguments, 1)?);
+    if separator.is_empty() {
+        let parts = s.into_iter().map(|g| Value::String(g.into())).collect();
+        return Ok(Value::List(Arc::new(parts)));
+    }
+    let mut parts = vec![];
+    let mut start = 0;
+    while let Some(end) = find(&s, &separator, start) {
+        parts.push(string(s[start..end].concat()));
+        start = end + separator.len();
+    }
+    parts.push(string(s[start..].concat()));
+    Ok(Value::List(Arc::new(parts)))
+}
+
+fn substr(arguments: Vec<Value>) -> Result<Value, String> {
+    let s = graphemes(string_argument("substr", &arguments, 0)?);
+    let start = integer_argument("substr", &arguments, 1)?;
+    let length = integer_argument("substr", &arguments, 2)?;
+    if start < 0 || start as u64 > s.len() as u64 {
+        return Err(format!(
+            "substr() start {} out of bounds for length {}",
+            start,
+            s.len()
+        ));
+    }
+    if length < 0 {
+        return Err(format!(
+            "substr() length must not be negative, got {}",
+            length
+        ));
+    }
+    let start = start as usize;
+    let end = start.saturating_add(length as usize).min(s.len());
+    Ok(string(s[start..end].concat()))
+}
+
+/// A `{...}` placeholder in a `format()` template
+struct Placeholder {
+    index: Option<usize>,
+    align: Option<char>,
+    width: usize,
+    precision: Option<usize>,
+}
+
+fn format(arguments: Vec<Value>) -> Result<Value, String> {
+    let template = string_argument("format", &arguments, 0)?;
+    let values = &arguments[1..];
+    let mut result = String::new();
+    let mut next = 0;
+    let mut chars = template.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '{' if chars.peek() == Some(&'{') => {
+                chars.next();
+                result.push('{');
+            }
+            '}' if chars.peek() == Some(&'}') => {
+                chars.next();
+                result.push('}');
+            }
+            '}' => return Err(String::from("format() found a '}' without a '{' before it")),
+            '{' => {
+                let placeholder = placeholder(&mut chars)?;
+                let index = placeholder.index.unwrap_or_else(|| {
+                    next += 1;
+                    next - 1
+                });
+                let value = values.get(index).ok_or_else(|| {
+                    format!(
+                        "format() template uses {{{}}}, but got {} value{}",
+                        index,
+                        values.len(),
+                        if values.len() == 1 { "" } else { "s" }
+                    )
+                })?;
+                result.push_str(&format_value(&placeholder, value, index + 1)?);
+            }
+            c => result.push(c),
+        }
+    }
+    Ok(string(result))
+}
+
+/// Parses `[index][:[align][width][.precision]]}`, after the opening brace
+fn placeholder(chars: &mut Peekable<Chars>) -> Result<Placeholder, String> {
+    let index = digits(chars)?;
+    let mut placeholder = Placeholder {
+        index,
+        align: None,
+        width: 0,
+        precision: None,
+    };
+    if chars.peek() == Some(&':') {
+        chars.next();
+        if let Some(align @ ('<' | '^' | '>')) = chars.peek().copied() {
+            chars.next();
+            placeholder.align = Some(align);
+        }
+        placeholder.width = digits(chars)?.unwrap_or(0);
+        if chars.peek() == Some(&'.') {
+            chars.next();
+            match digits(chars)? {
+                Some(precision) => placeholder.precision = Some(precision),
+                None => return Err(String::from("format() expects digits after '.'")),
+            }
+        }
+    }
+    match chars.next() {
+        Some('}') => Ok(placeholder),
+        Some(c) => Err(format!(
+            "format() found unexpected '{}' in a placeholder",
+            c
+        )),
+        None => Err(String::from("format() found a '{' without a '}' after it")),
+    }
+}
+
+fn digits(chars: &mut Peekable<Chars>) -> Result<Option<usize>, String> {
+    let mut digits = String::new();
+    while let Some(digit @ '0'..='9') = chars.peek().copied() {
+        chars.next();
+        digits.push(digit);
+    }
+    if digits.is_empty() {
+        return Ok(None);
+    }
+    match digits.parse() {
+        Ok(n) if n <= u16::MAX as usize => Ok(Some(n)),
+        _ => Err(format!("format() found {}, which is too large", digits)),
+    }
+}
+
+/// Numbers are padded on the left by default, and everything else on the right
+fn format_value(placeholder: &Placeholder, value: &Value, index: usize) -> Result<String, String> {
+    let text = match (placeholder.precision, value) {
+        (None, _) => stringify(value.clone()),
+        (Some(0), Value::Int(n)) => n.to_string(),
+        (Some(precision), Value::Int(n)) => format!("{}.{}", n, "0".repeat(precision)),
+        (Some(precision), Value::Number(n)) if n.is_finite() => {
+            format!("{:.*}", precision, n)
+        }
+        (Some(_), Value::Number(_)) => stringify(value.clone()),
+        (Some(_), _) => return Err(unexpected_argument("format", index, "a number", value)),
+    };
+    let padding = placeholder.width.saturating_sub(graphemes(&text).len());
+    let is_number = matches!(value, Value::Int(_) | Value::Number(_));
+    let (left, right) = match placeholder.align {
+        Some('<') => (0, padding),
+        Some('^') => (padding / 2, padding - padding / 2),
+        Some(_) => (padding, 0),
+        None if is_number => (padding, 0),
+        None => (0, padding),
+    };
+    Ok(format!("{}{}{}", " ".repeat(left), text, " ".repeat(right)))
+}
@@ -0,0 +1,454 @@
+use crate::expr::Expr;
+use crate::interpreter::{fold_binary, fold_unary, is_truthy};
+use crate::lexing::{Token, TokenKind};
+use crate::stmt::Stmt;
+use crate::symbol::Symbol;
+use crate::value::Value;
+
+/// Counters collected while optimizing, reported by `--stats`.
+#[derive(Debug, Default)]
+pub struct Stats {
+    pub nodes_before: usize,
+    pub nodes_after: usize,
+    pub peephole_rewrites: usize,
+    pub constant_folds: usize,
+}
+
+/// A pass rewrites a single node whose children have already been rewritten.
+type Pass = fn(Expr, &mut Stats) -> Expr;
+
+/// Passes run in order, each over the whole program.
+const PASSES: &[Pass] = &[peephole, fold];
+
+pub fn optimize(statements: Vec<Stmt>, stats: &mut Stats) -> Vec<Stmt> {
+    stats.nodes_before += statements.iter().map(count_stmt_nodes).sum::<usize>();
+    let mut statements = statements;
+    for pass in PASSES {
+        statements = statements
+            .into_iter()
+            .map(|stmt| match stmt {
+                Stmt::Assert {
+                    keyword,
+                    condition,
+                    message,
+                } => Stmt::Assert {
+                    keyword,
+                    condition: rewrite(condition, *pass, stats),
+                    message: message.map(|message| rewrite(message, *pass, stats)),
+                },
+                Stmt::Eprint { keyword, expr } => Stmt::Eprint {
+                    keyword,
+                    expr: rewrite(expr, *pass, stats),
+                },
+                Stmt::Expr(expr) => Stmt::Expr(rewrite(expr, *pass, stats)),
+                Stmt::Print { keyword, expr } => Stmt::Print {
+                    keyword,
+                    expr: rewrite(expr, *pass, stats),
+                },
+                Stmt::Var { name, initializer } => Stmt::Var {
+                    name,
+                    initializer: initializer.map(|initializer| rewrite(initializer, *pass, stats)),
+                },
+            })
+            .collect();
+    }
+    stats.nodes_after += statements.iter().map(count_stmt_nodes).sum::<usize>();
+    statements
+}
+
+/// Local rewrites that never change what a program prints or which errors it raises:
+///
+/// - negation of a literal is folded into the literal (`-1`, `!nil`)
+/// - `!(a == b)` becomes `a != b` and vice versa
+/// - double negation is removed when the operand already has the right type
+///   (`!!(a < b)`, `- -(a / b)`)
+/// - groupings are dropped, since the tree already encodes precedence
+fn peephole(mut expr: Expr, stats: &mut Stats) -> Expr {
+    let rewritten = match &mut expr {
+        Expr::Grouping { expr } => expr.take(),
+        Expr::Unary { op, right } => match (&op.kind, right.as_mut()) {
+            (
+                TokenKind::Minus,
+                Expr::Literal {
+                    value: Value::Number(n),
+                },
+            ) => Expr::Literal {
+                value: Value::Number(-*n),
+            },
+            (
+                TokenKind::Minus,
+                Expr::Literal {
+                    value: Value::Int(n),
+                },
+            ) if *n != i64::MIN => Expr::Literal {
+                value: Value::Int(-*n),
+            },
+            (TokenKind::Bang, Expr::Literal { value }) => Expr::Literal {
+                value: Value::Bool(!is_truthy(std::mem::take(value))),
+            },
+            (
+                TokenKind::Bang,
+                Expr::Binary {
+                    left,
+                    op: inner_op,
+                    right,
+                },
+            ) if is_equality(inner_op) => Expr::Binary {
+                left: Box::new(left.take()),
+                op: negate_equality(inner_op),
+                right: Box::new(right.take()),
+            },
+            (
+                TokenKind::Bang,
+                Expr::Unary {
+                    op: inner_op,
+                    right,
+                },
+            ) if matches!(inner_op.kind, TokenKind::Bang) && is_boolean(right) => right.take(),
+            (
+                TokenKind::Minus,
+                Expr::Unary {
+                    op: inner_op,
+                    right,
+                },
+            ) if matches!(inner_op.kind, TokenKind::Minus) && is_float(right) => right.take(),
+            _ => return expr,
+        },
+        _ => return expr,
+    };
+    stats.peephole_rewrites += 1;
+    rewritten
+}
+
+/// Evaluates operators whose operands are all literals, and picks the branch of a
+/// conditional (or `??`) whose condition is a literal (`2 * 3 + x` becomes `6 + x`).
+///
+/// Operations that would raise an error, or whose result depends on `Options`, are left
+/// for the interpreter to evaluate and report.
+fn fold(mut expr: Expr, stats: &mut Stats) -> Expr {
+    let folded = match &mut expr {
+        Expr::Binary { left, op, right } => match (left.as_ref(), right.as_ref()) {
+            (Expr::Literal { value: left }, Expr::Literal { value: right }) => {
+                match fold_binary(left, op, right) {
+                    Some(value) => Expr::Literal { value },
+                    None => return expr,
+                }
+            }
+            _ => return expr,
+        },
+        Expr::Conditional {
+            condition,
+            then_branch,
+            else_branch,
+        } => match condition.as_ref() {
+            Expr::Literal { value } if is_truthy(value.clone()) => then_branch.take(),
+            Expr::Literal { .. } => else_branch.take(),
+            _ => return expr,
+        },
+        Expr::Logical { left, op, right } => match left.as_mut() {
+            Expr::Literal { value } if matches!(op.kind, TokenKind::QuestionQuestion) => {
+                if matches!(value, Value::Nil) {
+                    right.take()
+                } else {
+                    left.take()
+                }
+            }
+            _ => return expr,
+        },
+        Expr::Unary { op, right } => match right.as_ref() {
+            Expr::Literal { value } => match fold_unary(op, value) {
+                Some(value) => Expr::Literal { value },
+                None => return expr,
+            },
+            _ => return expr,
+        },
+        _ => return expr,
+    };
+    stats.constant_folds += 1;
+    folded
+}
+
+fn is_equality(op: &Token) -> bool {
+    matches!(op.kind, TokenKind::BangEqual | TokenKind::EqualEqual)
+}
+
+fn negate_equality(op: &Token) -> Token {
+    let (kind, lexeme) = match op.kind {
+        TokenKind::EqualEqual => (TokenKind::BangEqual, "!="),
+        _ => (TokenKind::EqualEqual, "=="),
+    };
+    Token {
+        kind,
+        lexeme: Symbol::from(lexeme),
+        literal: None,
+        loc: op.loc.clone(),
+    }
+}
+
+/// Whether `expr` can only ever evaluate to a boolean (or raise an error).
+fn is_boolean(expr: &Expr) -> bool {
+    match expr {
+        Expr::Binary { op, .. } => matches!(
+            op.kind,
+            TokenKind::BangEqual
+                | TokenKind::EqualEqual
+                | TokenKind::Greater
+                | TokenKind::GreaterEqual
+                | TokenKind::Less
+                | TokenKind::LessEqual
+        ),
+        Expr::Literal {
+            value: Value::Bool(_),
+        } => true,
+        Expr::Unary { op, .. } => matches!(op.kind, TokenKind::Bang),
+        _ => false,
+    }
+}
+
+/// Whether `expr` can only ever evaluate to a float (or raise an error).
+///
+/// Integers don't count: negating `i64::MIN` overflows to a float, so `- -x` may differ
+/// from `x`.
+fn is_float(mut expr: &Expr) -> bool {
+    loop {
+        match expr {
+            Expr::Binary { op, .. } => return matches!(op.kind, TokenKind::Slash),
+            Expr::Literal {
+                value: Value::Number(_),
+            } => return true,
+            Expr::Unary { op, right } if matches!(op.kind, TokenKind::Minus) => expr = right,
+            _ => return false,
+        }
+    }
+}
+
+/// An expression node with its children taken out, waiting for them to be rewritten.
+enum Shape {
+    Binary { op: Token },
+    Call { paren: Token, len: usize },
+    Conditional,
+    Grouping,
+    Index { bracket: Token },
+    List { len: usize },
+    Logical { op: Token },
+    Map { brace: Token, len: usize },
+    Unary { op: Token },
+}
+
+enum Step {
+    Visit(Expr),
+    Build(Shape),
+}
+
+/// Applies `pass` to every node of `expr`, children first.
+///
+/// Like `evaluate`, this uses explicit stacks so deep trees can't overflow the host stack.
+fn rewrite(expr: Expr, pass: Pass, stats: &mut Stats) -> Expr {
+    let mut steps = vec![Step::Visit(expr)];
+    let mut done: Vec<Expr> = vec![];
+    while let Some(step) = steps.pop() {
+        match step {
+            // Children are taken out of the node, leaving an empty shell to drop
+            Step::Visit(mut expr) => match &mut expr {
+                Expr::Binary { left, op, right } => {
+                    steps.push(Step::Build(Shape::Binary { op: op.clone() }));
+                    steps.push(Step::Visit(right.take()));
+                    steps.push(Step::Visit(left.take()));
+                }
+                Expr::Call {
+                    callee,
+                    paren,
+                    arguments,
+                } => {
+                    steps.push(Step::Build(Shape::Call {
+                        paren: paren.clone(),
+                        len: arguments.len(),
+                    }));
+                    for argument in arguments.drain(..).rev() {
+                        steps.push(Step::Visit(argument));
+                    }
+                    steps.push(Step::Visit(callee.take()));
+                }
+                Expr::Conditional {
+                    condition,
+                    then_branch,
+                    else_branch,
+                } => {
+                    steps.push(Step::Build(Shape::Conditional));
+                    steps.push(Step::Visit(else_branch.take()));
+                    steps.push(Step::Visit(then_branch.take()));
+                    steps.push(Step::Visit(condition.take()));
+                }
+                Expr::Grouping { expr } => {
+                    steps.push(Step::Build(Shape::Grouping));
+                    steps.push(Step::Visit(expr.take()));
+                }
+                Expr::Index {
+                    object,
+                    bracket,
+                    index,
+                } => {
+                    steps.push(Step::Build(Shape::Index {
+                        bracket: bracket.clone(),
+                    }));
+                    steps.push(Step::Visit(index.take()));
+                    steps.push(Step::Visit(object.take()));
+                }
+                Expr::List { elements } => {
+                    steps.push(Step::Build(Shape::List {
+                        len: elements.len(),
+                    }));
+                    for element in elements.drain(..).rev() {
+                        steps.push(Step::Visit(element));
+                    }
+                }
+                Expr::Logical { left, op, right } => {
+                    steps.push(Step::Build(Shape::Logical { op: op.clone() }));
+                    steps.push(Step::Visit(right.take()));
+                    steps.push(Step::Visit(left.take()));
+                }
+                Expr::Map { brace, entries } => {
+                    steps.push(Step::Build(Shape::Map {
+                        brace: brace.clone(),
+                        len: entries.len(),
+                    }));
+                    for (key, value) in entries.drain(..).rev() {
+                        steps.push(Step::Visit(value));
+                        steps.push(Step::Visit(key));
+                    }
+                }
+                Expr::Unary { op, right } => {
+                    steps.push(Step::Build(Shape::Unary { op: op.clone() }));
+                    steps.push(Step::Visit(right.take()));
+                }
+                Expr::Literal { .. } | Expr::Variable { .. } => done.push(pass(expr, stats)),
+            },
+            Step::Build(shape) => {
+                let node = match shape {
+                    Shape::Binary { op } => {
+                        let right = pop_rewritten(&mut done);
+                        let left = pop_rewritten(&mut done);
+                        Expr::Binary { left, op, right }
+                    }
+                    Shape::Call { paren, len } => {
+                        let arguments = done.split_off(done.len() - len);
+                        let callee = pop_rewritten(&mut done);
+                        Expr::Call {
+                            callee,
+                            paren,
+                            arguments,
+                        }
+                    }
+                    Shape::Conditional => {
+                        let else_branch = pop_rewritten(&mut done);
+                        let then_branch = pop_rewritten(&mut done);
+                        let condition = pop_rewritten(&mut done);
+                        Expr::Conditional {
+                            condition,
+                            then_branch,
+                            else_branch,
+                        }
+                    }
+                    Shape::Grouping => Expr::Grouping {
+                        expr: pop_rewritten(&mut done),
+                    },
+                    Shape::Index { bracket } => {
+                        let index = pop_rewritten(&mut done);
+                        let object = pop_rewritten(&mut done);
+                        Expr::Index {
+                            object,
+                            bracket,
+                            index,
+                        }
+                    }
+                    Shape::List { len } => Expr::List {
+                        elements: done.split_off(done.len() - len),
+                    },
+                    Shape::Logical { op } => {
+                        let right = pop_rewritten(&mut done);
+                        let left = pop_rewritten(&mut done);
+                        Expr::Logical { left, op, right }
+                    }
+                    Shape::Map { brace, len } => {
+                        let mut flat = done.split_off(done.len() - 2 * len).into_iter();
+                        let mut entries = vec![];
+                        while let (Some(key), Some(value)) = (flat.next(), flat.next()) {
+                            entries.push((key, value));
+                        }
+                        Expr::Map { brace, entries }
+                    }
+                    Shape::Unary { op } => Expr::Unary {
+                        op,
+                        right: pop_rewritten(&mut done),
+                    },
+                };
+                done.push(pass(node, stats));
+            }
+        }
+    }
+    *pop_rewritten(&mut done)
+}
+
+fn pop_rewritten(done: &mut Vec<Expr>) -> Box<Expr> {
+    match done.pop() {
+        Some(expr) => Box::new(expr),
+        None => panic!("Rewrite stack underflow while optimizing. This is a bug."),
+    }
+}
+
+fn count_stmt_nodes(stmt: &Stmt) -> usize {
+    match stmt {
+        Stmt::Assert {
+            condition, message, ..
+        } => count_nodes(condition) + message.as_ref().map_or(0, count_nodes),
+        Stmt::Eprint { expr, .. } | Stmt::Expr(expr) | Stmt::Print { expr, .. } => {
+            count_nodes(expr)
+        }
+        Stmt::Var { initializer, .. } => initializer.as_ref().map_or(0, count_nodes),
+    }
+}
+
+fn count_nodes(expr: &Expr) -> usize {
+    let mut pending = vec![expr];
+    let mut count = 0;
+    while let Some(expr) = pending.pop() {
+        count += 1;
+        match expr {
+            Expr::Binary { left, right, .. } | Expr::Logical { left, right, .. } => {
+                pending.push(left);
+                pending.push(right);
+            }
+            Expr::Call {
+                callee, arguments, ..
+            } => {
+                pending.push(callee);
+                pending.extend(arguments);
+            }
+            Expr::Conditional {
+                condition,
+                then_branch,
+                else_branch,
+            } => {
+                pending.push(condition);
+                pending.push(then_branch);
+                pending.push(else_branch);
+            }
+            Expr::Grouping { expr } => pending.push(expr),
+            Expr::Index { object, index, .. } => {
+                pending.push(object);
+                pending.push(index);
+            }
+            Expr::List { elements } => pending.extend(elements),
+            Expr::Literal { .. } => (),
+            Expr::Map { entries, .. } => {
+                for (key, value) in entries {
+                    pending.push(key);
+                    pending.push(value);
+                }
+            }
+            Expr::Unary { right, .. } => pending.push(right),
+            Expr::Variable { .. } => (),
+        }
+    }
+    count
+}
@@ -1,6 +1,8 @@
 use crate::expr::Expr;
-use crate::lexing::{Token, TokenKind};
+use crate::lexing::{Loc, Token, TokenKind};
 use crate::stmt::Stmt;
+use crate::symbol::Symbol;
+use crate::value::Value;
 use std::iter::Peekable;
 use std::slice::Iter;
 
@@ -10,8 +12,81 @@ pub struct ParsingError {
     token: Token,
 }
 
-pub fn parse(tokens: &Vec<Token>) -> Result<Vec<Stmt>, ParsingError> {
-    let mut it = tokens.iter().peekable();
+impl std::fmt::Display for ParsingError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self.token.kind {
+            TokenKind::Eof => write!(f, "[{}] Error at end: {}", self.token.loc, self.message),
+            _ => write!(
+                f,
+                "[{}] Error at '{}': {}",
+                self.token.loc, self.token.lexeme, self.message
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ParsingError {}
+
+impl ParsingError {
+    /// Whether parsing failed only because the tokens ran out mid-statement
+    pub fn is_unexpected_eof(&self) -> bool {
+        matches!(self.token.kind, TokenKind::Eof)
+    }
+}
+
+/// How deeply groupings, collections, calls, conditionals and prefix operators may nest.
+///
+/// The parser recurses once per level, so this keeps hostile input from overflowing the stack.
+pub const MAX_NESTING: usize = 100;
+
+/// How many arguments a single call may pass
+pub const MAX_ARGUMENTS: usize = 255;
+
+/// The tokens left to parse and how deeply nested the parser currently is
+struct Tokens<'a> {
+    tokens: Peekable<Iter<'a, Token>>,
+    previous: Option<&'a Token>,
+    depth: usize,
+}
+
+impl<'a> Tokens<'a> {
+    fn peek(&mut self) -> Option<&&'a Token> {
+        self.tokens.peek()
+    }
+
+    fn next(&mut self) -> Option<&'a Token> {
+        let next = self.tokens.next();
+        if next.is_some() {
+            self.previous = next;
+        }
+        next
+    }
+}
+
+pub fn parse(tokens: &[Token]) -> Result<Vec<Stmt>, ParsingError> {
+    // Every rule can rely on seeing `Eof` before running out of tokens
+    match tokens.last() {
+        Some(Token {
+            kind: TokenKind::Eof,
+            ..
+        }) => (),
+        last => {
+            return Err(ParsingError {
+                message: String::from("Syntax error: tokens must end with EOF"),
+                token: Token {
+                    kind: TokenKind::Eof,
+                    lexeme: Symbol::from(""),
+                    literal: None,
+                    loc: last.map_or(Loc::single(1), |token| token.loc.clone()),
+                },
+            })
+        }
+    }
+    let mut it = Tokens {
+        tokens: tokens.iter().peekable(),
+        previous: None,
+        depth: 0,
+    };
     let mut statements: Vec<Stmt> = vec![];
     loop {
         let stmt = match it.peek() {
@@ -23,8 +98,29 @@ pub fn parse(tokens: &Vec<Token>) -> Result<Vec<Stmt>, ParsingError> {
                 kind: TokenKind::Print,
                 ..
             }) => {
-                it.next(); // consume the peeked print token
-                print_statement(&mut it)
+                let keyword = it.next().unwrap();
+                print_statement(&mut it, keyword)
+            }
+            Some(Token {
+                kind: TokenKind::Assert,
+                ..
+            }) => {
+                let keyword = it.next().unwrap();
+                assert_statement(&mut it, keyword)
+            }
+            Some(Token {
+                kind: TokenKind::Eprint,
+                ..
+            }) => {
+                let keyword = it.next().unwrap();
+                eprint_statement(&mut it, keyword)
+            }
+            Some(Token {
+                kind: TokenKind::Var,
+                ..
+            }) => {
+                it.next(); // consume the peeked var token
+                var_declaration(&mut it)
             }
             _ => expression_statement(&mut it),
         };
@@ -33,23 +129,142 @@ pub fn parse(tokens: &Vec<Token>) -> Result<Vec<Stmt>, ParsingError> {
     Ok(statements)
 }
 
-fn expression_statement(it: &mut Peekable<Iter<Token>>) -> Result<Stmt, ParsingError> {
+fn assert_statement(it: &mut Tokens, keyword: &Token) -> Result<Stmt, ParsingError> {
+    let condition = expression(it)?;
+    let message = match it.peek() {
+        Some(Token {
+            kind: TokenKind::Comma,
+            ..
+        }) => {
+            it.next(); // consume the peeked comma
+            Some(expression(it)?)
+        }
+        _ => None,
+    };
+    expect_semicolon(it)?;
+    Ok(Stmt::Assert {
+        keyword: keyword.clone(),
+        condition,
+        message,
+    })
+}
+
+fn expression_statement(it: &mut Tokens) -> Result<Stmt, ParsingError> {
     let expr = expression(it)?;
     expect_semicolon(it)?;
     Ok(Stmt::Expr(expr))
 }
 
-fn print_statement(it: &mut Peekable<Iter<Token>>) -> Result<Stmt, ParsingError> {
+fn print_statement(it: &mut Tokens, keyword: &Token) -> Result<Stmt, ParsingError> {
     let expr = expression(it)?;
     expect_semicolon(it)?;
-    Ok(Stmt::Print(expr))
+    Ok(Stmt::Print {
+        keyword: keyword.clone(),
+        expr,
+    })
 }
 
-fn expression(it: &mut Peekable<Iter<Token>>) -> Result<Expr, ParsingError> {
-    equality(it)
+fn eprint_statement(it: &mut Tokens, keyword: &Token) -> Result<Stmt, ParsingError> {
+    let expr = expression(it)?;
+    expect_semicolon(it)?;
+    Ok(Stmt::Eprint {
+        keyword: keyword.clone(),
+        expr,
+    })
 }
 
-fn equality(it: &mut Peekable<Iter<Token>>) -> Result<Expr, ParsingError> {
+fn var_declaration(it: &mut Tokens) -> Result<Stmt, ParsingError> {
+    let name = match it.next() {
+        Some(name) if matches!(name.kind, TokenKind::Identifier) => name.clone(),
+        Some(token) => {
+            return Err(ParsingError {
+                message: String::from("Syntax error: expected variable name"),
+                token: token.clone(),
+            })
+        }
+        None => panic!("Unexpected end of tokens. This is a bug."),
+    };
+    let initializer = match it.peek() {
+        Some(Token {
+            kind: TokenKind::Equal,
+            ..
+        }) => {
+            it.next(); // consume the peeked equal sign
+            Some(expression(it)?)
+        }
+        _ => None,
+    };
+    expect_semicolon(it)?;
+    Ok(Stmt::Var { name, initializer })
+}
+
+fn expression(it: &mut Tokens) -> Result<Expr, ParsingError> {
+    nested(it, conditional)
+}
+
+/// Runs `rule` one nesting level deeper, failing once `MAX_NESTING` is reached
+fn nested(
+    it: &mut Tokens,
+    rule: fn(&mut Tokens) -> Result<Expr, ParsingError>,
+) -> Result<Expr, ParsingError> {
+    if it.depth == MAX_NESTING {
+        return match it.peek() {
+            Some(token) => Err(ParsingError {
+                message: format!(
+                    "Syntax error: expression nested more than {} levels deep",
+                    MAX_NESTING
+                ),
+                token: (*token).clone(),
+            }),
+            None => panic!("Unexpected end of tokens. This is a bug."),
+        };
+    }
+    it.depth += 1;
+    let result = rule(it);
+    it.depth -= 1;
+    result
+}
+
+fn conditional(it: &mut Tokens) -> Result<Expr, ParsingError> {
+    let condition = nil_coalescing(it)?;
+    if let Some(Token {
+        kind: TokenKind::Question,
+        ..
+    }) = it.peek()
+    {
+        it.next(); // consume the peeked question mark
+        let then_branch = expression(it)?;
+        expect_colon(it, "Syntax error: expected ':' in conditional expression")?;
+        let else_branch = nested(it, conditional)?;
+        Ok(Expr::Conditional {
+            condition: Box::new(condition),
+            then_branch: Box::new(then_branch),
+            else_branch: Box::new(else_branch),
+        })
+    } else {
+        Ok(condition)
+    }
+}
+
+fn nil_coalescing(it: &mut Tokens) -> Result<Expr, ParsingError> {
+    let mut left = equality(it)?;
+    while let Some(Token {
+        kind: TokenKind::QuestionQuestion,
+        ..
+    }) = it.peek()
+    {
+        let op = it.next().unwrap();
+        let right = equality(it)?;
+        left = Expr::Logical {
+            left: Box::new(left),
+            op: op.clone(),
+            right: Box::new(right),
+        };
+    }
+    Ok(left)
+}
+
+fn equality(it: &mut Tokens) -> Result<Expr, ParsingError> {
     let mut left = comparison(it)?;
     while let Some(Token {
         kind: TokenKind::BangEqual | TokenKind::EqualEqual,
@@ -67,12 +282,104 @@ fn equality(it: &mut Peekable<Iter<Token>>) -> Result<Expr, ParsingError> {
     Ok(left)
 }
 
-fn comparison(it: &mut Peekable<Iter<Token>>) -> Result<Expr, ParsingError> {
-    let mut left = term(it)?;
+fn comparison(it: &mut Tokens) -> Result<Expr, ParsingError> {
+    let mut left = range(it)?;
     while let Some(Token {
         kind: TokenKind::Greater | TokenKind::GreaterEqual | TokenKind::Less | TokenKind::LessEqual,
         ..
     }) = it.peek()
+    {
+        let op = it.next().unwrap();
+        let right = range(it)?;
+        left = Expr::Binary {
+            left: Box::new(left),
+            op: op.clone(),
+            right: Box::new(right),
+        };
+    }
+    Ok(left)
+}
+
+/// Ranges don't chain: `1..2..3` is a syntax error
+fn range(it: &mut Tokens) -> Result<Expr, ParsingError> {
+    let left = bit_or(it)?;
+    if let Some(Token {
+        kind: TokenKind::DotDot | TokenKind::DotDotEqual,
+        ..
+    }) = it.peek()
+    {
+        let op = it.next().unwrap();
+        let right = bit_or(it)?;
+        Ok(Expr::Binary {
+            left: Box::new(left),
+            op: op.clone(),
+            right: Box::new(right),
+        })
+    } else {
+        Ok(left)
+    }
+}
+
+fn bit_or(it: &mut Tokens) -> Result<Expr, ParsingError> {
+    let mut left = bit_xor(it)?;
+    while let Some(Token {
+        kind: TokenKind::Pipe,
+        ..
+    }) = it.peek()
+    {
+        let op = it.next().unwrap();
+        let right = bit_xor(it)?;
+        left = Expr::Binary {
+            left: Box::new(left),
+            op: op.clone(),
+            right: Box::new(right),
+        };
+    }
+    Ok(left)
+}
+
+fn bit_xor(it: &mut Tokens) -> Result<Expr, ParsingError> {
+    let mut left = bit_and(it)?;
+    while let Some(Token {
+        kind: TokenKind::Caret,
+        ..
+    }) = it.peek()
+    {
+        let op = it.next().unwrap();
+        let right = bit_and(it)?;
+        left = Expr::Binary {
+            left: Box::new(left),
+            op: op.clone(),
+            right: Box::new(right),
+        };
+    }
+    Ok(left)
+}
+
+fn bit_and(it: &mut Tokens) -> Result<Expr, ParsingError> {
+    let mut left = shift(it)?;
+    while let Some(Token {
+        kind: TokenKind::Ampersand,
+        ..
+    }) = it.peek()
+    {
+        let op = it.next().unwrap();
+        let right = shift(it)?;
+        left = Expr::Binary {
+            left: Box::new(left),
+            op: op.clone(),
+            right: Box::new(right),
+        };
+    }
+    Ok(left)
+}
+
+fn shift(it: &mut Tokens) -> Result<Expr, ParsingError> {
+    let mut left = term(it)?;
+    while let Some(Token {
+        kind: TokenKind::GreaterGreater | TokenKind::LessLess,
+        ..
+    }) = it.peek()
     {
         let op = it.next().unwrap();
         let right = term(it)?;
@@ -85,7 +392,7 @@ fn comparison(it: &mut Peekable<Iter<Token>>) -> Result<Expr, ParsingError> {
     Ok(left)
 }
 
-fn term(it: &mut Peekable<Iter<Token>>) -> Result<Expr, ParsingError> {
+fn term(it: &mut Tokens) -> Result<Expr, ParsingError> {
     let mut left = factor(it)?;
     while let Some(Token {
         kind: TokenKind::Minus | TokenKind::Plus,
@@ -103,10 +410,10 @@ fn term(it: &mut Peekable<Iter<Token>>) -> Result<Expr, ParsingError> {
     Ok(left)
 }
 
-fn factor(it: &mut Peekable<Iter<Token>>) -> Result<Expr, ParsingError> {
+fn factor(it: &mut Tokens) -> Result<Expr, ParsingError> {
     let mut left = unary(it)?;
     while let Some(Token {
-        kind: TokenKind::Slash | TokenKind::Star,
+        kind: TokenKind::Percent | TokenKind::Slash | TokenKind::Star,
         ..
     }) = it.peek()
     {
@@ -121,24 +428,78 @@ fn factor(it: &mut Peekable<Iter<Token>>) -> Result<Expr, ParsingError> {
     Ok(left)
 }
 
-fn unary(it: &mut Peekable<Iter<Token>>) -> Result<Expr, ParsingError> {
+fn unary(it: &mut Tokens) -> Result<Expr, ParsingError> {
     if let Some(Token {
-        kind: TokenKind::Bang | TokenKind::Minus,
+        kind: TokenKind::Bang | TokenKind::Minus | TokenKind::Tilde,
         ..
     }) = it.peek()
     {
         let op = it.next().unwrap();
-        let right = unary(it)?;
+        let right = nested(it, unary)?;
         Ok(Expr::Unary {
             op: op.clone(),
             right: Box::new(right),
         })
     } else {
-        primary(it)
+        power(it)
+    }
+}
+
+fn power(it: &mut Tokens) -> Result<Expr, ParsingError> {
+    let left = call(it)?;
+    if let Some(Token {
+        kind: TokenKind::StarStar,
+        ..
+    }) = it.peek()
+    {
+        let op = it.next().unwrap();
+        // Recursing through unary makes `**` right-associative and allows `2 ** -1`
+        let right = nested(it, unary)?;
+        Ok(Expr::Binary {
+            left: Box::new(left),
+            op: op.clone(),
+            right: Box::new(right),
+        })
+    } else {
+        Ok(left)
     }
 }
 
-fn primary(it: &mut Peekable<Iter<Token>>) -> Result<Expr, ParsingError> {
+fn call(it: &mut Tokens) -> Result<Expr, ParsingError> {
+    let mut expr = primary(it)?;
+    loop {
+        match it.peek() {
+            Some(Token {
+                kind: TokenKind::LeftParen,
+                ..
+            }) => {
+                let paren = it.next().unwrap();
+                let arguments = arguments(it)?;
+                expr = Expr::Call {
+                    callee: Box::new(expr),
+                    paren: paren.clone(),
+                    arguments,
+                };
+            }
+            Some(Token {
+                kind: TokenKind::LeftBracket,
+                ..
+            }) => {
+                let bracket = it.next().unwrap();
+                let index = expression(it)?;
+                expect_closing_bracket(it)?;
+                expr = Expr::Index {
+                    object: Box::new(expr),
+                    bracket: bracket.clone(),
+                    index: Box::new(index),
+                };
+            }
+            _ => return Ok(expr),
+        }
+    }
+}
+
+fn primary(it: &mut Tokens) -> Result<Expr, ParsingError> {
     match it.next() {
         Some(Token {
             kind:
@@ -150,8 +511,11 @@ fn primary(it: &mut Peekable<Iter<Token>>) -> Result<Expr, ParsingError> {
             literal,
             ..
         }) => Ok(Expr::Literal {
-            value: literal.clone(),
+            value: literal.clone().map_or(Value::Nil, Value::from),
         }),
+        Some(name) if matches!(name.kind, TokenKind::Identifier) => {
+            Ok(Expr::Variable { name: name.clone() })
+        }
         Some(open_paren) if matches!(open_paren.kind, TokenKind::LeftParen) => {
             let expr = expression(it)?;
             expect_closing_paren(it)?;
@@ -159,6 +523,17 @@ fn primary(it: &mut Peekable<Iter<Token>>) -> Result<Expr, ParsingError> {
                 expr: Box::new(expr),
             })
         }
+        Some(open_bracket) if matches!(open_bracket.kind, TokenKind::LeftBracket) => {
+            let elements = list_elements(it)?;
+            Ok(Expr::List { elements })
+        }
+        Some(open_brace) if matches!(open_brace.kind, TokenKind::LeftBrace) => {
+            let entries = map_entries(it)?;
+            Ok(Expr::Map {
+                brace: open_brace.clone(),
+                entries,
+            })
+        }
         Some(eof) if matches!(eof.kind, TokenKind::Eof) => Err(ParsingError {
             message: String::from("Syntax error: expected primary expression, got EOF"),
             token: eof.clone(),
@@ -171,7 +546,122 @@ fn primary(it: &mut Peekable<Iter<Token>>) -> Result<Expr, ParsingError> {
     }
 }
 
-fn expect_closing_paren(it: &mut Peekable<Iter<Token>>) -> Result<(), ParsingError> {
+fn list_elements(it: &mut Tokens) -> Result<Vec<Expr>, ParsingError> {
+    let mut elements = vec![];
+    loop {
+        if let Some(Token {
+            kind: TokenKind::RightBracket,
+            ..
+        }) = it.peek()
+        {
+            break;
+        }
+        elements.push(expression(it)?);
+        match it.peek() {
+            Some(Token {
+                kind: TokenKind::Comma,
+                ..
+            }) => {
+                it.next(); // consume the peeked comma, a trailing one is allowed
+            }
+            _ => break,
+        }
+    }
+    expect_closing_bracket(it)?;
+    Ok(elements)
+}
+
+fn arguments(it: &mut Tokens) -> Result<Vec<Expr>, ParsingError> {
+    let mut arguments = vec![];
+    loop {
+        if let Some(Token {
+            kind: TokenKind::RightParen,
+            ..
+        }) = it.peek()
+        {
+            break;
+        }
+        if arguments.len() == MAX_ARGUMENTS {
+            return Err(ParsingError {
+                message: format!(
+                    "Syntax error: can't have more than {} arguments",
+                    MAX_ARGUMENTS
+                ),
+                token: (*it.peek().unwrap()).clone(),
+            });
+        }
+        arguments.push(expression(it)?);
+        match it.peek() {
+            Some(Token {
+                kind: TokenKind::Comma,
+                ..
+            }) => {
+                it.next(); // consume the peeked comma, a trailing one is allowed
+            }
+            _ => break,
+        }
+    }
+    expect_closing_paren(it)?;
+    Ok(arguments)
+}
+
+fn map_entries(it: &mut Tokens) -> Result<Vec<(Expr, Expr)>, ParsingError> {
+    let mut entries = vec![];
+    loop {
+        if let Some(Token {
+            kind: TokenKind::RightBrace,
+            ..
+        }) = it.peek()
+        {
+            break;
+        }
+        let key = expression(it)?;
+        expect_colon(it, "Syntax error: expected ':' after map key")?;
+        let value = expression(it)?;
+        entries.push((key, value));
+        match it.peek() {
+            Some(Token {
+                kind: TokenKind::Comma,
+                ..
+            }) => {
+                it.next(); // consume the peeked comma, a trailing one is allowed
+            }
+            _ => break,
+        }
+    }
+    expect_closing_brace(it)?;
+    Ok(entries)
+}
+
+fn expect_closing_brace(it: &mut Tokens) -> Result<(), ParsingError> {
+    match it.next() {
+        Some(Token {
+            kind: TokenKind::RightBrace,
+            ..
+        }) => Ok(()),
+        Some(not_close_brace) => Err(ParsingError {
+            message: String::from("Syntax error: expected '}'"),
+            token: not_close_brace.clone(),
+        }),
+        None => panic!("Unexpected end of tokens. This is a bug."),
+    }
+}
+
+fn expect_closing_bracket(it: &mut Tokens) -> Result<(), ParsingError> {
+    match it.next() {
+        Some(Token {
+            kind: TokenKind::RightBracket,
+            ..
+        }) => Ok(()),
+        Some(not_close_bracket) => Err(ParsingError {
+            message: String::from("Syntax error: expected ']'"),
+            token: not_close_bracket.clone(),
+        }),
+        None => panic!("Unexpected end of tokens. This is a bug."),
+    }
+}
+
+fn expect_closing_paren(it: &mut Tokens) -> Result<(), ParsingError> {
     match it.next() {
         Some(Token {
             kind: TokenKind::RightParen,
@@ -185,16 +675,76 @@ fn expect_closing_paren(it: &mut Peekable<Iter<Token>>) -> Result<(), ParsingErr
     }
 }
 
-fn expect_semicolon(it: &mut Peekable<Iter<Token>>) -> Result<(), ParsingError> {
+fn expect_colon(it: &mut Tokens, message: &str) -> Result<(), ParsingError> {
     match it.next() {
         Some(Token {
-            kind: TokenKind::Semicolon,
+            kind: TokenKind::Colon,
             ..
         }) => Ok(()),
-        Some(not_semicolon) => Err(ParsingError {
+        Some(not_colon) => Err(ParsingError {
+            message: String::from(message),
+            token: not_colon.clone(),
+        }),
+        None => panic!("Unexpected end of tokens. This is a bug."),
+    }
+}
+
+fn expect_semicolon(it: &mut Tokens) -> Result<(), ParsingError> {
+    let previous = it.previous;
+    match (it.next(), previous) {
+        (
+            Some(Token {
+                kind: TokenKind::Semicolon,
+                ..
+            }),
+            _,
+        ) => Ok(()),
+        // Keep pointing at EOF so the REPL still asks for the rest of the statement
+        (
+            Some(
+                eof @ Token {
+                    kind: TokenKind::Eof,
+                    ..
+                },
+            ),
+            Some(previous),
+        ) => Err(ParsingError {
+            message: format!("Syntax error: expected ';' after '{}'", previous.lexeme),
+            token: eof.clone(),
+        }),
+        // The statement looks complete and the next one already started, so point at the
+        // end of the expression instead of the start of the next statement
+        (Some(next), Some(previous))
+            if next.loc.line_begin > previous.loc.line_end || starts_statement(next) =>
+        {
+            Err(ParsingError {
+                message: format!(
+                    "Syntax error: missing ';' after '{}'; insert one at the end of line {}",
+                    previous.lexeme, previous.loc.line_end
+                ),
+                token: previous.clone(),
+            })
+        }
+        (Some(not_semicolon), _) => Err(ParsingError {
             message: String::from("Syntax error: expected ';'"),
             token: not_semicolon.clone(),
         }),
-        None => panic!("Unexpected end of tokens. This is a bug."),
+        (None, _) => panic!("Unexpected end of tokens. This is a bug."),
     }
 }
+
+fn starts_statement(token: &Token) -> bool {
+    matches!(
+        token.kind,
+        TokenKind::Assert
+            | TokenKind::Class
+            | TokenKind::Eprint
+            | TokenKind::For
+            | TokenKind::Fun
+            | TokenKind::If
+            | TokenKind::Print
+            | TokenKind::Return
+            | TokenKind::Var
+            | TokenKind::While
+    )
+}
@@ -0,0 +1,133 @@
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::sync::Arc;
+
+use crate::interpreter::{Halt, Interpreter, Limit, Options, RuntimeError};
+use crate::lexing::{LexingError, Scanner};
+use crate::optimizing::{optimize, Stats};
+use crate::parsing::{parse, ParsingError};
+use crate::stmt::Stmt;
+use crate::value::Value;
+
+#[derive(Debug)]
+pub enum Diagnostics {
+    Lexing(LexingError),
+    Parsing(ParsingError),
+}
+
+impl std::fmt::Display for Diagnostics {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Diagnostics::Lexing(error) => write!(f, "{}", error),
+            Diagnostics::Parsing(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl std::error::Error for Diagnostics {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Diagnostics::Lexing(error) => Some(error),
+            Diagnostics::Parsing(error) => Some(error),
+        }
+    }
+}
+
+/// A scanned, parsed and optimized script that can be run any number of times.
+///
+/// Cloning only bumps a reference count, and programs can be sent to other threads.
+#[derive(Clone, Debug)]
+pub struct Program {
+    statements: Arc<[Stmt]>,
+}
+
+impl Program {
+    pub fn compile(source: &str) -> Result<Program, Diagnostics> {
+        let tokens = Scanner::new(source).scan().map_err(Diagnostics::Lexing)?;
+        let statements = parse(&tokens).map_err(Diagnostics::Parsing)?;
+        let statements = optimize(statements, &mut Stats::default());
+        Ok(Program {
+            statements: statements.into(),
+        })
+    }
+
+    pub fn statements(&self) -> &[Stmt] {
+        &self.statements
+    }
+
+    /// Runs the program with a fresh `Interpreter`, see `Interpreter::execute` to keep
+    /// globals, limits and hooks across runs.
+    ///
+    /// Returns the value of the last statement when it is an expression
+    pub fn run(&self, options: &Options) -> Result<Option<Value>, Halt> {
+        Interpreter::new(options.clone()).execute(self)
+    }
+}
+
+/// Why `run_no_panic` stopped
+#[derive(Debug)]
+pub enum RunError {
+    Compile(Diagnostics),
+    Runtime(RuntimeError),
+    /// The program called `exit()` with this status
+    Exit(i32),
+    /// The program ran into one of the limits set in `Options`
+    Limit(Limit),
+    /// A panic escaped the pipeline, which is a bug in rlox; holds the panic message
+    Internal(String),
+}
+
+impl std::fmt::Display for RunError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            RunError::Compile(diagnostics) => write!(f, "{}", diagnostics),
+            RunError::Runtime(error) => write!(f, "{}", error),
+            RunError::Exit(code) => write!(f, "Exited with status {}", code),
+            RunError::Limit(limit) => write!(f, "{}", limit),
+            RunError::Internal(message) => write!(f, "Internal error: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for RunError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            RunError::Compile(diagnostics) => Some(diagnostics),
+            RunError::Runtime(error) => Some(error),
+            RunError::Exit(_) | RunError::Internal(_) | RunError::Limit(_) => None,
+        }
+    }
+}
+
+/// Compiles and runs `source`, reporting every failure as a `RunError` instead of aborting.
+///
+/// Scanning, parsing and evaluation return typed errors for any input, and nesting is
+/// limited to `parsing::MAX_NESTING` levels so hostile source can't exhaust a thread's
+/// stack, as long as it has at least 4 MiB (1 MiB for release builds). Any panic left
+/// over from a bug is caught and returned as `RunError::Internal`, though that last
+/// line of defense needs the default `panic = "unwind"` strategy.
+///
+/// Succeeds with the value of the last statement when it is an expression, so `"1 + 2;"`
+/// gives `3`.
+pub fn run_no_panic(source: &str, options: &Options) -> Result<Option<Value>, RunError> {
+    let result = catch_unwind(AssertUnwindSafe(|| {
+        let program = Program::compile(source).map_err(RunError::Compile)?;
+        program.run(options).map_err(|halt| match halt {
+            Halt::Error(error) => RunError::Runtime(error),
+            Halt::Exit(code) => RunError::Exit(code),
+            Halt::Limit(limit) => RunError::Limit(limit),
+        })
+    }));
+    match result {
+        Ok(result) => result,
+        Err(payload) => {
+            let message = match payload.downcast::<String>() {
+                Ok(message) => *message,
+                Err(payload) => match payload.downcast::<&str>() {
+                    Ok(message) => String::from(*message),
+                    Err(_) => String::from("panic without a message"),
+                },
+            };
+            Err(RunError::Internal(message))
+        }
+    }
+}
@@ -0,0 +1,345 @@
+//! Saving global variables to bytes and reading them back.
+//!
+//! A snapshot starts with a header line, followed by one `name value` entry per global.
+//! Every value starts with a one-letter tag:
+//!
+//! - `n`, `t`, `f`: nil, true, false
+//! - `i42;`: an integer
+//! - `d4000000000000000;`: a float, as the hex digits of its bits so it reads back exactly
+//! - `s5:hello`: a string, prefixed with its length in bytes
+//! - `l2;` and `m2;`: a list of 2 elements, or a map of 2 key-value pairs, which follow
+//! - `r0;3;` and `R0;3;`: the ranges `0..3` and `0..=3`
+//! - `F3:len`: a builtin function, looked up by name when restoring
+//!
+//! Names are written like strings, and entries end with a line break, so a snapshot
+//! stays readable enough to inspect.
+
+use std::collections::BTreeMap;
+use std::io::{self, Read, Write};
+use std::sync::Arc;
+
+use crate::callable::Callable;
+use crate::interpreter::Limit;
+use crate::value::{MapKey, Range, Value};
+
+const HEADER: &str = "rlox snapshot 1\n";
+
+#[derive(Debug)]
+pub enum SnapshotError {
+    Io(io::Error),
+    /// The bytes aren't a snapshot this version can read
+    Invalid(String),
+    /// Restoring would go over one of the interpreter's limits
+    Limit(Limit),
+}
+
+impl std::fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            SnapshotError::Io(error) => write!(f, "Cannot access snapshot: {}", error),
+            SnapshotError::Invalid(message) => write!(f, "Invalid snapshot: {}", message),
+            SnapshotError::Limit(limit) => write!(f, "Cannot restore snapshot: {}", limit),
+        }
+    }
+}
+
+impl std::error::Error for SnapshotError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            SnapshotError::Io(error) => Some(error),
+            SnapshotError::Invalid(_) | SnapshotError::Limit(_) => None,
+        }
+    }
+}
+
+impl From<io::Error> for SnapshotError {
+    fn from(error: io::Error) -> SnapshotError {
+        SnapshotError::Io(error)
+    }
+}
+
+pub(crate) fn write<'a>(
+    globals: impl Iterator<Item = (&'a str, &'a Value)>,
+    out: &mut impl Write,
+) -> io::Result<()> {
+    let mut bytes = Vec::from(HEADER);
+    for (name, value) in globals {
+        write_str(b's', name, &mut bytes);
+        bytes.push(b' ');
+        write_value(value, &mut bytes);
+        bytes.push(b'\n');
+    }
+    out.write_all(&bytes)
+}
+
+/// Something left to write: a value, or a map key, which is written like one
+enum Item<'a> {
+    Value(&'a Value),
+    Key(&'a MapKey),
+}
+
+/// Writes `value` with an explicit stack, so deeply nested lists can't overflow
+fn write_value(value: &Value, bytes: &mut Vec<u8>) {
+    let mut pending = vec![Item::Value(value)];
+    while let Some(item) = pending.pop() {
+        let value = match item {
+            Item::Value(value) => value,
+            Item::Key(MapKey::Int(n)) => {
+                bytes.extend(format!("i{};", n).as_bytes());
+                continue;
+            }
+            Item::Key(MapKey::Number(n)) => {
+                bytes.extend(format!("d{:x};", n.to_bits()).as_bytes());
+                continue;
+            }
+            Item::Key(MapKey::String(s)) => {
+                write_str(b's', s, bytes);
+                continue;
+            }
+        };
+        match value {
+            Value::Nil => bytes.push(b'n'),
+            Value::Bool(true) => bytes.push(b't'),
+            Value::Bool(false) => bytes.push(b'f'),
+            Value::Function(function) => write_str(b'F', function.name(), bytes),
+            Value::Int(n) => bytes.extend(format!("i{};", n).as_bytes()),
+            Value::List(elements) => {
+                bytes.extend(format!("l{};", elements.len()).as_bytes());
+                pending.extend(elements.iter().rev().map(Item::Value));
+            }
+            Value::Map(map) => {
+                bytes.extend(format!("m{};", map.len()).as_bytes());
+                for (key, value) in map.iter().rev() {
+                    pending.push(Item::Value(value));
+                    pending.push(Item::Key(key));
+                }
+            }
+            Value::Number(n) => bytes.extend(format!("d{:x};", n.to_bits()).as_bytes()),
+            Value::Range(range) => {
+                let tag = if range.inclusive { 'R' } else { 'r' };
+                bytes.extend(format!("{}{};{};", tag, range.start, range.end).as_bytes());
+            }
+            Value::String(s) => write_str(b's', s, bytes),
+        }
+    }
+}
+
+fn write_str(tag: u8, s: &str, bytes: &mut Vec<u8>) {
+    bytes.push(tag);
+    bytes.extend(format!("{}:", s.len()).as_bytes());
+    bytes.extend(s.as_bytes());
+}
+
+/// Reads back the globals `write` wrote, finding builtin functions with `builtin`
+pub(crate) fn read(
+    input: &mut impl Read,
+    builtin: impl Fn(&str) -> Option<Arc<dyn Callable>>,
+) -> Result<Vec<(String, Value)>, SnapshotError> {
+    let mut bytes = vec![];
+    input.read_to_end(&mut bytes)?;
+    let mut reader = Reader {
+        bytes: &bytes,
+        at: 0,
+    };
+    reader.expect(HEADER)?;
+    let mut globals = vec![];
+    while reader.at < bytes.len() {
+        reader.expect("s")?;
+        let name = reader.string()?;
+        reader.expect(" ")?;
+        let value = reader.value(&builtin)?;
+        reader.expect("\n")?;
+        globals.push((name, value));
+    }
+    Ok(globals)
+}
+
+/// A list or map being read, waiting for `remaining` more values
+enum Frame {
+    List {
+        remaining: usize,
+        elements: Vec<Value>,
+    },
+    Map {
+        remaining: usize,
+        entries: BTreeMap<MapKey, Value>,
+        key: Option<MapKey>,
+    },
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    at: usize,
+}
+
+impl<'a> Reader<'a> {
+    /// Reads a value with an explicit stack of the collections it is nested in
+    fn value(
+        &mut self,
+        builtin: &impl Fn(&str) -> Option<Arc<dyn Callable>>,
+    ) -> Result<Value, SnapshotError> {
+        let mut frames: Vec<Frame> = vec![];
+        loop {
+            let tag = self.byte()?;
+            let mut value = match tag {
+                b'n' => Value::Nil,
+                b't' => Value::Bool(true),
+                b'f' => Value::Bool(false),
+                b'i' => Value::Int(self.number()?),
+                b'd' => {
+                    let bits = self.until(b';')?;
+                    match u64::from_str_radix(bits, 16) {
+                        Ok(bits) => Value::Number(f64::from_bits(bits)),
+                        Err(_) => return Err(invalid(format!("bad float bits '{}'", bits))),
+                    }
+                }
+                b's' => Value::String(self.string()?.into()),
+                b'F' => {
+                    let name = self.string()?;
+                    match builtin(&name) {
+                        Some(function) => Value::Function(function),
+                        None => return Err(invalid(format!("unknown function '{}'", name))),
+                    }
+                }
+                b'r' | b'R' => Value::Range(Range {
+                    start: self.number()?,
+                    end: self.number()?,
+                    inclusive: tag == b'R',
+                }),
+                b'l' | b'm' => {
+                    let remaining = self.number()?;
+                    if remaining > 0 {
+                        frames.push(if tag == b'l' {
+                            Frame::List {
+                                remaining,
+                                elements: Vec::with_capacity(remaining.min(1024)),
+                            }
+                        } else {
+                            Frame::Map {
+                                remaining,
+                                entries: BTreeMap::new(),
+                                key: None,
+                            }
+                        });
+                        continue;
+                    }
+                    if tag == b'l' {
+                        Value::List(Arc::default())
+                    } else {
+                        Value::Map(Arc::default())
+                    }
+                }
+                other => {
+                    return Err(invalid(format!(
+                        "unexpected '{}' at byte {}",
+                        other as char,
+                        self.at - 1
+                    )))
+                }
+            };
+            // Hands the value to the innermost collection, closing each one it completes
+            loop {
+                let complete = match frames.last_mut() {
+                    None => return Ok(value),
+                    Some(Frame::List {
+                        remaining,
+                        elements,
+                    }) => {
+                        elements.push(value);
+                        *remaining -= 1;
+                        *remaining == 0
+                    }
+                    Some(Frame::Map {
+                        remaining,
+                        entries,
+                        key,
+                    }) => match key.take() {
+                        None => {
+                            match MapKey::from_value(&value) {
+                                Some(map_key) => *key = Some(map_key),
+                                None => return Err(invalid(String::from("bad map key"))),
+                            }
+                            false
+                        }
+                        Some(map_key) => {
+                            entries.insert(map_key, value);
+                            *remaining -= 1;
+                            *remaining == 0
+                        }
+                    },
+                };
+                if !complete {
+                    break;
+                }
+                value = match frames.pop() {
+                    Some(Frame::List { elements, .. }) => Value::List(Arc::new(elements)),
+                    Some(Frame::Map { entries, .. }) => Value::Map(Arc::new(entries)),
+                    None => unreachable!("a frame was just completed"),
+                };
+            }
+        }
+    }
+
+    fn byte(&mut self) -> Result<u8, SnapshotError> {
+        match self.bytes.get(self.at) {
+            Some(byte) => {
+                self.at += 1;
+                Ok(*byte)
+            }
+            None => Err(invalid(String::from("unexpected end"))),
+        }
+    }
+
+    fn expect(&mut self, expected: &str) -> Result<(), SnapshotError> {
+        if self.bytes[self.at..].starts_with(expected.as_bytes()) {
+            self.at += expected.len();
+            Ok(())
+        } else {
+            Err(invalid(format!(
+                "expected {:?} at byte {}",
+                expected, self.at
+            )))
+        }
+    }
+
+    /// The text up to `end`, which is skipped too
+    fn until(&mut self, end: u8) -> Result<&'a str, SnapshotError> {
+        let bytes = self.bytes;
+        let len = match bytes[self.at..].iter().position(|byte| *byte == end) {
+            Some(len) => len,
+            None => return Err(invalid(format!("expected {:?}", end as char))),
+        };
+        let text = &bytes[self.at..self.at + len];
+        self.at += len + 1;
+        std::str::from_utf8(text).map_err(|_| invalid(String::from("bad UTF-8")))
+    }
+
+    /// A number ended by `;`
+    fn number<T: std::str::FromStr>(&mut self) -> Result<T, SnapshotError> {
+        let text = self.until(b';')?;
+        text.parse()
+            .map_err(|_| invalid(format!("bad number '{}'", text)))
+    }
+
+    /// A length, a `:` and that many bytes of UTF-8
+    fn string(&mut self) -> Result<String, SnapshotError> {
+        let len: usize = {
+            let text = self.until(b':')?;
+            text.parse()
+                .map_err(|_| invalid(format!("bad length '{}'", text)))?
+        };
+        let end = self.at.saturating_add(len);
+        match self.bytes.get(self.at..end).map(std::str::from_utf8) {
+            Some(Ok(text)) => {
+                self.at = end;
+                Ok(String::from(text))
+            }
+            Some(Err(_)) => Err(invalid(String::from("bad UTF-8"))),
+            None => Err(invalid(String::from("unexpected end"))),
+        }
+    }
+}
+
+fn invalid(message: String) -> SnapshotError {
+    SnapshotError::Invalid(message)
+}
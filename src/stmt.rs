@@ -1,7 +1,47 @@
 use crate::expr::Expr;
+use crate::lexing::Token;
 
 #[derive(Debug)]
 pub enum Stmt {
+    Assert {
+        keyword: Token,
+        condition: Expr,
+        message: Option<Expr>,
+    },
+    /// Like `Print`, but writes to stderr
+    Eprint {
+        keyword: Token,
+        expr: Expr,
+    },
     Expr(Expr),
-    Print(Expr),
+    Print {
+        keyword: Token,
+        expr: Expr,
+    },
+    /// Declares a global variable, set to `nil` without an initializer
+    Var {
+        name: Token,
+        initializer: Option<Expr>,
+    },
+}
+
+impl std::fmt::Display for Stmt {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Stmt::Assert {
+                condition,
+                message: Some(message),
+                ..
+            } => write!(f, "(assert {} {})", condition, message),
+            Stmt::Assert { condition, .. } => write!(f, "(assert {})", condition),
+            Stmt::Eprint { expr, .. } => write!(f, "(eprint {})", expr),
+            Stmt::Expr(expr) => write!(f, "{}", expr),
+            Stmt::Print { expr, .. } => write!(f, "(print {})", expr),
+            Stmt::Var {
+                name,
+                initializer: Some(initializer),
+            } => write!(f, "(var {} {})", name.lexeme, initializer),
+            Stmt::Var { name, .. } => write!(f, "(var {})", name.lexeme),
+        }
+    }
 }
@@ -0,0 +1,512 @@
+use std::cmp::Ordering;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::BTreeMap;
+use std::hash::{Hash, Hasher};
+use std::sync::Arc;
+
+use crate::callable::Callable;
+use crate::lexing::LiteralValue;
+
+/// A runtime value. Compares with Lox's `==`, see `lox_eq`.
+///
+/// Strings and collections are behind `Arc`s, so cloning a value, as assigning or passing
+/// it does, only copies a handle. Nothing mutates them in place, so sharing is invisible.
+#[derive(Clone, Debug, Default)]
+pub enum Value {
+    #[default]
+    Nil,
+    Bool(bool),
+    Function(Arc<dyn Callable>),
+    Int(i64),
+    List(Arc<Vec<Value>>),
+    Map(Arc<BTreeMap<MapKey, Value>>),
+    Number(f64),
+    Range(Range),
+    String(Arc<str>),
+}
+
+impl From<LiteralValue> for Value {
+    fn from(literal: LiteralValue) -> Value {
+        match literal {
+            LiteralValue::Bool(b) => Value::Bool(b),
+            LiteralValue::Int(n) => Value::Int(n),
+            LiteralValue::Number(n) => Value::Number(n),
+            LiteralValue::String(s) => Value::String(s.into()),
+        }
+    }
+}
+
+/// Lox's `==`: integers and floats compare by numeric value, collections compare
+/// element by element, and values of different types are never equal.
+pub fn lox_eq(lhs: &Value, rhs: &Value) -> bool {
+    lhs == rhs
+}
+
+/// `==` with `Options::coerce_equality`: like `lox_eq`, except that a number also equals
+/// a string holding the same number, so `1 == "1"` and `2 == " 2.0"`.
+pub fn coercing_eq(lhs: &Value, rhs: &Value) -> bool {
+    match (lhs, rhs) {
+        (Value::String(s), number) | (number, Value::String(s)) if as_number(number).is_some() => {
+            parse_number(s).is_some_and(|parsed| parsed == *number)
+        }
+        _ => lox_eq(lhs, rhs),
+    }
+}
+
+/// Parses a string like `"3.14"` or `" 42 "` into a number, as `number()` does
+pub(crate) fn parse_number(s: &str) -> Option<Value> {
+    let s = s.trim();
+    match s.parse::<i64>() {
+        Ok(int) => Some(Value::Int(int)),
+        Err(_) => s.parse::<f64>().ok().map(Value::Number),
+    }
+}
+
+/// Roughly how many bytes `value` takes, counting the strings and collections it holds,
+/// even ones shared with other values
+pub fn approx_size(value: &Value) -> usize {
+    let mut pending = vec![value];
+    let mut size = 0;
+    while let Some(value) = pending.pop() {
+        size += std::mem::size_of::<Value>();
+        match value {
+            Value::List(elements) => {
+                size += (elements.capacity() - elements.len()) * std::mem::size_of::<Value>();
+                pending.extend(elements.iter());
+            }
+            Value::Map(map) => {
+                for (key, value) in map.iter() {
+                    size += std::mem::size_of::<MapKey>();
+                    if let MapKey::String(key) = key {
+                        size += key.capacity();
+                    }
+                    pending.push(value);
+                }
+            }
+            Value::String(s) => size += s.len(),
+            _ => (),
+        }
+    }
+    size
+}
+
+/// A total order for sorting values: `nil` < booleans < numbers < strings < lists < maps
+/// < ranges < functions.
+///
+/// Numbers compare exactly, even large integers against floats, `-0.0` equals `0.0` and
+/// NaN sorts after every other number. Collections compare lexicographically. Functions
+/// are equal only to themselves, in an order that is arbitrary but fixed within a process.
+pub fn lox_cmp(lhs: &Value, rhs: &Value) -> Ordering {
+    // Collections are walked with an explicit stack, so deep nesting can't overflow
+    let mut pending = vec![Step::Compare(lhs, rhs)];
+    while let Some(step) = pending.pop() {
+        let ordering = match step {
+            Step::Decided(ordering) => ordering,
+            Step::Compare(Value::List(lhs), Value::List(rhs)) => {
+                pending.push(Step::Decided(lhs.len().cmp(&rhs.len())));
+                let pairs = lhs.iter().zip(rhs.iter()).rev();
+                pending.extend(pairs.map(|(lhs, rhs)| Step::Compare(lhs, rhs)));
+                continue;
+            }
+            Step::Compare(Value::Map(lhs), Value::Map(rhs)) => {
+                pending.push(Step::Decided(lhs.len().cmp(&rhs.len())));
+                for ((lhs_key, lhs), (rhs_key, rhs)) in lhs.iter().zip(rhs.iter()).rev() {
+                    pending.push(Step::Compare(lhs, rhs));
+                    pending.push(Step::Decided(lhs_key.cmp(rhs_key)));
+                }
+                continue;
+            }
+            Step::Compare(lhs, rhs) => cmp_shallow(lhs, rhs),
+        };
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+    }
+    Ordering::Equal
+}
+
+/// What `lox_cmp` has left to do, in the order it pops them
+enum Step<'a> {
+    Compare(&'a Value, &'a Value),
+    Decided(Ordering),
+}
+
+/// `lox_cmp` for anything but two lists or two maps
+fn cmp_shallow(lhs: &Value, rhs: &Value) -> Ordering {
+    match (lhs, rhs) {
+        (Value::Bool(lhs), Value::Bool(rhs)) => lhs.cmp(rhs),
+        (Value::String(lhs), Value::String(rhs)) => lhs.cmp(rhs),
+        (Value::Function(lhs), Value::Function(rhs)) => address(lhs).cmp(&address(rhs)),
+        (Value::Range(lhs), Value::Range(rhs)) => lhs.key().cmp(&rhs.key()),
+        (lhs, rhs) => match (as_number(lhs), as_number(rhs)) {
+            (Some(lhs), Some(rhs)) => match (lhs.is_nan(), rhs.is_nan()) {
+                (false, false) => compare_numbers(lhs, rhs).unwrap_or(Ordering::Equal),
+                (lhs, rhs) => lhs.cmp(&rhs),
+            },
+            _ => rank(lhs).cmp(&rank(rhs)),
+        },
+    }
+}
+
+/// A hash consistent with `lox_cmp`: values it orders as equal hash the same,
+/// so `1` and `1.0` do, and so does anything `lox_eq` considers equal.
+///
+/// The hash is stable within a process, not across Rust versions.
+pub fn lox_hash(value: &Value) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    hash_value(value, &mut hasher);
+    hasher.finish()
+}
+
+/// Wraps a value so it can be used as a key in `HashMap`s and `BTreeMap`s.
+///
+/// Equality is `lox_cmp` returning `Equal`. Unlike `lox_eq`, that makes NaN equal to
+/// itself, as keys need to be.
+#[derive(Clone, Debug)]
+pub struct ValueKey(pub Value);
+
+impl PartialEq for ValueKey {
+    fn eq(&self, other: &ValueKey) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for ValueKey {}
+
+impl PartialOrd for ValueKey {
+    fn partial_cmp(&self, other: &ValueKey) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for ValueKey {
+    fn cmp(&self, other: &ValueKey) -> Ordering {
+        lox_cmp(&self.0, &other.0)
+    }
+}
+
+impl Hash for ValueKey {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        hash_value(&self.0, state)
+    }
+}
+
+/// Strings and numbers can be map keys. Integral floats are stored as `Int`,
+/// so `m[1]` and `m[1.0]` refer to the same entry.
+#[derive(Clone, Debug)]
+pub enum MapKey {
+    Int(i64),
+    Number(f64),
+    String(String),
+}
+
+impl MapKey {
+    pub fn from_value(value: &Value) -> Option<MapKey> {
+        match value {
+            Value::Int(n) => Some(MapKey::Int(*n)),
+            Value::Number(n) if n.is_nan() => None,
+            Value::Number(n)
+                if n.fract() == 0.0 && *n >= i64::MIN as f64 && *n < i64::MAX as f64 =>
+            {
+                Some(MapKey::Int(*n as i64))
+            }
+            Value::Number(n) => Some(MapKey::Number(*n)),
+            Value::String(s) => Some(MapKey::String(String::from(&**s))),
+            _ => None,
+        }
+    }
+
+    pub fn to_value(&self) -> Value {
+        match self {
+            MapKey::Int(n) => Value::Int(*n),
+            MapKey::Number(n) => Value::Number(*n),
+            MapKey::String(s) => Value::String(s.as_str().into()),
+        }
+    }
+}
+
+impl PartialEq for MapKey {
+    fn eq(&self, other: &MapKey) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for MapKey {}
+
+impl PartialOrd for MapKey {
+    fn partial_cmp(&self, other: &MapKey) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for MapKey {
+    /// Numbers sort before strings
+    fn cmp(&self, other: &MapKey) -> Ordering {
+        match (self, other) {
+            (MapKey::Int(lhs), MapKey::Int(rhs)) => lhs.cmp(rhs),
+            (MapKey::Int(lhs), MapKey::Number(rhs)) => {
+                compare_numbers(Number::Int(*lhs), Number::Float(*rhs)).unwrap_or(Ordering::Equal)
+            }
+            (MapKey::Number(lhs), MapKey::Int(rhs)) => {
+                compare_numbers(Number::Float(*lhs), Number::Int(*rhs)).unwrap_or(Ordering::Equal)
+            }
+            (MapKey::Number(lhs), MapKey::Number(rhs)) => lhs.total_cmp(rhs),
+            (MapKey::String(lhs), MapKey::String(rhs)) => lhs.cmp(rhs),
+            (_, MapKey::String(_)) => Ordering::Less,
+            (MapKey::String(_), _) => Ordering::Greater,
+        }
+    }
+}
+
+/// The integers from `start` up or down to `end`, which is included only if `inclusive`.
+///
+/// `0..3` is 0, 1, 2 and `3..0` is 3, 2, 1.
+#[derive(Clone, Copy, Debug)]
+pub struct Range {
+    pub start: i64,
+    pub end: i64,
+    pub inclusive: bool,
+}
+
+impl Range {
+    /// The number of elements, saturating for `i64::MIN..=i64::MAX`
+    pub fn len(&self) -> u64 {
+        let distance = (self.end as i128 - self.start as i128).unsigned_abs() as u64;
+        if self.inclusive {
+            distance.saturating_add(1)
+        } else {
+            distance
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The element at `index`, counting from `start`
+    pub fn get(&self, index: i64) -> Option<i64> {
+        if index < 0 || index as u64 >= self.len() {
+            return None;
+        }
+        let element = if self.start <= self.end {
+            self.start as i128 + index as i128
+        } else {
+            self.start as i128 - index as i128
+        };
+        Some(element as i64)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = i64> {
+        let range = *self;
+        (0..range.len()).map(move |index| range.get(index as i64).unwrap())
+    }
+
+    /// Ranges are compared by the elements they produce, so `0..3` equals `0..=2`
+    /// and every empty range is the same
+    fn key(&self) -> (u64, i64, bool) {
+        let len = self.len();
+        let start = if len == 0 { 0 } else { self.start };
+        (len, start, len > 1 && self.start > self.end)
+    }
+}
+
+/// Where a function lives, which is its identity: two functions are the same only if
+/// they are one and the same, whatever their names
+pub(crate) fn address(function: &Arc<dyn Callable>) -> usize {
+    Arc::as_ptr(function) as *const () as usize
+}
+
+fn rank(value: &Value) -> u8 {
+    match value {
+        Value::Nil => 0,
+        Value::Bool(_) => 1,
+        Value::Int(_) | Value::Number(_) => 2,
+        Value::String(_) => 3,
+        Value::List(_) => 4,
+        Value::Map(_) => 5,
+        Value::Range(_) => 6,
+        Value::Function(_) => 7,
+    }
+}
+
+/// An integer or float, kept apart so integers beyond 2^53 don't lose precision
+#[derive(Clone, Copy)]
+pub(crate) enum Number {
+    Int(i64),
+    Float(f64),
+}
+
+impl Number {
+    fn is_nan(self) -> bool {
+        matches!(self, Number::Float(n) if n.is_nan())
+    }
+}
+
+pub(crate) fn as_number(value: &Value) -> Option<Number> {
+    match value {
+        Value::Int(n) => Some(Number::Int(*n)),
+        Value::Number(n) => Some(Number::Float(*n)),
+        _ => None,
+    }
+}
+
+/// Compares two numbers exactly; `None` when either is NaN
+pub(crate) fn compare_numbers(lhs: Number, rhs: Number) -> Option<Ordering> {
+    match (lhs, rhs) {
+        (Number::Int(lhs), Number::Int(rhs)) => Some(lhs.cmp(&rhs)),
+        (Number::Float(lhs), Number::Float(rhs)) => lhs.partial_cmp(&rhs),
+        (Number::Int(lhs), Number::Float(rhs)) => compare_int_float(lhs, rhs),
+        (Number::Float(lhs), Number::Int(rhs)) => {
+            compare_int_float(rhs, lhs).map(Ordering::reverse)
+        }
+    }
+}
+
+fn compare_int_float(int: i64, float: f64) -> Option<Ordering> {
+    if float.is_nan() {
+        None
+    } else if float >= i64::MAX as f64 {
+        // i64::MAX as f64 rounds up to 2^63, which no i64 reaches
+        Some(Ordering::Less)
+    } else if float < i64::MIN as f64 {
+        Some(Ordering::Greater)
+    } else {
+        let whole = float.trunc();
+        match int.cmp(&(whole as i64)) {
+            Ordering::Equal => 0.0.partial_cmp(&(float - whole)),
+            ordering => Some(ordering),
+        }
+    }
+}
+
+fn hash_value<H: Hasher>(value: &Value, state: &mut H) {
+    let mut pending = vec![Node::Value(value)];
+    while let Some(node) = pending.pop() {
+        let value = match node {
+            Node::Key(key) => {
+                hash_shallow(&key.to_value(), state);
+                continue;
+            }
+            Node::Value(value) => value,
+        };
+        match value {
+            Value::List(elements) => {
+                rank(value).hash(state);
+                elements.len().hash(state);
+                pending.extend(elements.iter().rev().map(Node::Value));
+            }
+            Value::Map(map) => {
+                rank(value).hash(state);
+                map.len().hash(state);
+                for (key, value) in map.iter().rev() {
+                    pending.push(Node::Value(value));
+                    pending.push(Node::Key(key));
+                }
+            }
+            _ => hash_shallow(value, state),
+        }
+    }
+}
+
+/// Something `hash_value` has left to hash
+enum Node<'a> {
+    Value(&'a Value),
+    Key(&'a MapKey),
+}
+
+/// `hash_value` for anything but a list or map
+fn hash_shallow<H: Hasher>(value: &Value, state: &mut H) {
+    rank(value).hash(state);
+    match value {
+        Value::Nil | Value::List(_) | Value::Map(_) => (),
+        Value::Bool(b) => b.hash(state),
+        Value::Int(n) => n.hash(state),
+        Value::Number(n) => hash_float(*n, state),
+        Value::String(s) => s.hash(state),
+        Value::Function(function) => address(function).hash(state),
+        Value::Range(range) => range.key().hash(state),
+    }
+}
+
+/// Integral floats hash like the integer they equal, and every NaN hashes the same
+fn hash_float<H: Hasher>(n: f64, state: &mut H) {
+    if n.is_nan() {
+        u64::MAX.hash(state);
+    } else if n.fract() == 0.0 && n >= i64::MIN as f64 && n < i64::MAX as f64 {
+        (n as i64).hash(state);
+    } else {
+        n.to_bits().hash(state);
+    }
+}
+
+impl PartialEq for Value {
+    fn eq(&self, other: &Value) -> bool {
+        let mut pending = vec![(self, other)];
+        while let Some(pair) = pending.pop() {
+            match pair {
+                (Value::List(lhs), Value::List(rhs)) => {
+                    if lhs.len() != rhs.len() {
+                        return false;
+                    }
+                    pending.extend(lhs.iter().zip(rhs.iter()));
+                }
+                (Value::Map(lhs), Value::Map(rhs)) => {
+                    if lhs.len() != rhs.len() {
+                        return false;
+                    }
+                    for ((lhs_key, lhs), (rhs_key, rhs)) in lhs.iter().zip(rhs.iter()) {
+                        if lhs_key != rhs_key {
+                            return false;
+                        }
+                        pending.push((lhs, rhs));
+                    }
+                }
+                (Value::Function(lhs), Value::Function(rhs)) => {
+                    if !Arc::ptr_eq(lhs, rhs) {
+                        return false;
+                    }
+                }
+                (lhs, rhs) => {
+                    let equal = match (as_number(lhs), as_number(rhs)) {
+                        (Some(lhs), Some(rhs)) => {
+                            compare_numbers(lhs, rhs) == Some(Ordering::Equal)
+                        }
+                        _ => cmp_shallow(lhs, rhs) == Ordering::Equal,
+                    };
+                    if !equal {
+                        return false;
+                    }
+                }
+            }
+        }
+        true
+    }
+}
+
+/// Takes apart lists and maps nothing else shares with an explicit stack, so dropping a
+/// deeply nested value (like one built by repeating `var a = [a];`) can't overflow
+impl Drop for Value {
+    fn drop(&mut self) {
+        let mut pending = vec![];
+        take_children(self, &mut pending);
+        while let Some(mut value) = pending.pop() {
+            take_children(&mut value, &mut pending);
+        }
+    }
+}
+
+fn take_children(value: &mut Value, pending: &mut Vec<Value>) {
+    match value {
+        Value::List(elements) => {
+            if let Some(elements) = Arc::get_mut(elements) {
+                pending.append(elements);
+            }
+        }
+        Value::Map(map) => {
+            if let Some(map) = Arc::get_mut(map) {
+                pending.extend(std::mem::take(map).into_values());
+            }
+        }
+        _ => (),
+    }
+}
@@ -0,0 +1,52 @@
+use rlox::lexing::{LexingError, Scanner, Token};
+
+const CORPUS: &[&str] = &[
+    "print 1 + 2 * 3;",
+    "print \"hello\" + \" world\"; // comment\nprint nil ?? true;",
+    "print [1, 2.5, 0x1F, 0b101, 1e3, 2.5E-3][0];\nprint {\"a\": 1, 2: [3]};",
+    "/* block /* nested */\n comment */ print 1 ** 2 % 3 << 4 >> 1 & 7 | 8 ^ ~9;",
+    "print 1 ? \"a\\\"b\" : 2;\r\nprint 3;\r\n// crlf comment\r\nprint \"multi\r\nline\";",
+    "print 1.2.3;",
+    "print (0..3)[1.5..=-2];",
+    "print \"unterminated",
+    "print @;",
+    "",
+];
+
+fn scan(scanner: Scanner) -> String {
+    let result: Result<Vec<Token>, LexingError> = scanner.scan();
+    format!("{:?}", result)
+}
+
+#[test]
+fn ascii_fast_path_matches_grapheme_segmentation() {
+    for source in CORPUS {
+        assert_eq!(
+            scan(Scanner::new(source)),
+            scan(Scanner::new(source).without_ascii_fast_path()),
+            "token output differs for {:?}",
+            source
+        );
+    }
+}
+
+#[test]
+fn non_ascii_source_still_scans_graphemes() {
+    let source = "print \"naïve 👍🏽\";";
+    assert_eq!(
+        scan(Scanner::new(source)),
+        scan(Scanner::new(source).without_ascii_fast_path())
+    );
+}
+
+#[test]
+fn repeated_lexemes_share_storage() {
+    let tokens = Scanner::new("count = count + \"a\" + \"a\";")
+        .scan()
+        .unwrap();
+    let shared = |i: usize, j: usize| tokens[i].lexeme.as_ptr() == tokens[j].lexeme.as_ptr();
+    assert!(shared(0, 2));
+    assert!(shared(4, 6));
+    assert!(shared(3, 5));
+    assert!(!shared(0, 4));
+}
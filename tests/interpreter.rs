@@ -0,0 +1,796 @@
+use std::io::{self, Write};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use rlox::callable::Callable;
+use rlox::hooks::Hooks;
+use rlox::interpreter::{interpret, stringify, Halt, Interpreter, Limit, Options};
+use rlox::lexing::{Loc, Scanner};
+use rlox::parsing::parse;
+use rlox::program::Program;
+use rlox::stmt::Stmt;
+use rlox::value::Value;
+
+fn run(interpreter: &mut Interpreter, source: &str) -> Result<Option<Value>, Halt> {
+    interpreter.execute(&Program::compile(source).unwrap())
+}
+
+/// What `source` evaluates to, debug-formatted so integers and floats can be told apart
+fn evaluate(source: &str) -> String {
+    let mut interpreter = Interpreter::new(Options::default());
+    format!("{:?}", run(&mut interpreter, source).unwrap().unwrap())
+}
+
+/// The error `source` stops with under `options`
+fn error_with(options: Options, source: &str) -> String {
+    let mut interpreter = Interpreter::new(options);
+    run(&mut interpreter, source).unwrap_err().to_string()
+}
+
+#[test]
+fn globals_persist_across_runs_of_one_interpreter() {
+    let mut interpreter = Interpreter::new(Options::default());
+    run(&mut interpreter, "var x = 1; var y;").unwrap();
+    run(&mut interpreter, "assert x == 1; assert y == nil;").unwrap();
+
+    // A failing run keeps what it defined before the error
+    assert!(run(&mut interpreter, "var z = x + 1; undefined;").is_err());
+    run(&mut interpreter, "assert z == 2; var x = \"shadowed\";").unwrap();
+    run(&mut interpreter, "assert x == \"shadowed\";").unwrap();
+
+    let program = Program::compile("assert x == 1;").unwrap();
+    assert!(matches!(
+        interpret(program.statements(), &Options::default()),
+        Err(Halt::Error(_))
+    ));
+}
+
+#[test]
+fn a_compiled_program_runs_against_a_long_lived_interpreter() {
+    let mut interpreter = Interpreter::new(Options::default());
+    run(&mut interpreter, "var count = 0;").unwrap();
+    let program = Program::compile("var count = count + 1; count;").unwrap();
+    for expected in 1..=3 {
+        let value = interpreter.execute(&program).unwrap();
+        assert!(matches!(value, Some(Value::Int(n)) if n == expected));
+    }
+}
+
+#[test]
+fn calls_with_the_wrong_number_of_arguments_fail() {
+    let cases = [
+        ("len();", "len() expects 1 argument, got 0"),
+        ("len(1, 2);", "len() expects 1 argument, got 2"),
+        (
+            "jsonStringify();",
+            "jsonStringify() expects 1 to 2 arguments, got 0",
+        ),
+        ("format();", "format() expects at least 1 argument, got 0"),
+        ("clock(1);", "clock() expects 0 arguments, got 1"),
+    ];
+    for (source, message) in cases.iter() {
+        let mut interpreter = Interpreter::new(Options::default());
+        let error = format!("{:?}", run(&mut interpreter, source).unwrap_err());
+        assert!(error.contains(message), "{} gave {}", source, error);
+    }
+}
+
+#[test]
+fn strict_math_rejects_zero_divisors() {
+    let strict = Options {
+        strict_math: true,
+        ..Options::default()
+    };
+    for source in ["var z = 0; 1 / z;", "1 % 0;", "1.5 / -0.0;", "0 / 0;"].iter() {
+        let mut interpreter = Interpreter::new(strict.clone());
+        let error = format!("{:?}", run(&mut interpreter, source).unwrap_err());
+        assert!(
+            error.contains("Division by zero"),
+            "{} gave {}",
+            source,
+            error
+        );
+        let mut interpreter = Interpreter::new(Options::default());
+        run(&mut interpreter, source).unwrap();
+    }
+    let mut interpreter = Interpreter::new(strict);
+    run(&mut interpreter, "assert 7 / 2 == 3.5; assert 7 % 2 == 1;").unwrap();
+}
+
+#[test]
+fn equality_coerces_only_when_asked_to() {
+    let mut strict = Interpreter::new(Options::default());
+    run(
+        &mut strict,
+        "var one = \"1\"; assert 1 != one; assert !(1 == \"1\");",
+    )
+    .unwrap();
+    let mut coercing = Interpreter::new(Options {
+        coerce_equality: true,
+        ..Options::default()
+    });
+    run(
+        &mut coercing,
+        "var one = \"1\"; assert 1 == one; assert \" 2.5 \" == 2.5; assert 1 != \"one\"; \
+         assert [1] != [\"1\"]; assert nil != \"nil\";",
+    )
+    .unwrap();
+}
+
+/// A `Write` the test keeps a handle to after giving it to an interpreter
+#[derive(Clone, Default)]
+struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+impl Buffer {
+    fn contents(&self) -> String {
+        String::from_utf8(self.0.lock().unwrap().clone()).unwrap()
+    }
+}
+
+impl Write for Buffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Fails every write, like a closed pipe
+struct Closed;
+
+impl Write for Closed {
+    fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+        Err(io::Error::new(io::ErrorKind::BrokenPipe, "closed"))
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn print_and_eprint_write_to_the_given_sinks() {
+    let (stdout, stderr) = (Buffer::default(), Buffer::default());
+    let mut interpreter = Interpreter::new(Options::default())
+        .with_stdout(stdout.clone())
+        .with_stderr(stderr.clone());
+    run(
+        &mut interpreter,
+        "print 1 + 1; eprint \"oops\"; print [nil];",
+    )
+    .unwrap();
+    assert_eq!(stdout.contents(), "2\n[nil]\n");
+    assert_eq!(stderr.contents(), "oops\n");
+
+    let mut interpreter = Interpreter::new(Options::default()).with_stdout(Closed);
+    let error = format!("{:?}", run(&mut interpreter, "print 1;").unwrap_err());
+    assert!(error.contains("print failed: closed"), "{}", error);
+}
+
+#[test]
+fn errors_display_their_line_and_message() {
+    let cases = [
+        (
+            "var x = 1;\nprint -nil;",
+            "[line 2] Error: Unary operator - expects a numeric operand",
+        ),
+        (
+            "print (1;",
+            "[line 1] Error at ';': Syntax error: expected ')'",
+        ),
+        (
+            "print 1 +",
+            "[line 1] Error at end: Syntax error: expected primary expression, got EOF",
+        ),
+        ("\n\nprint @;", "[line 3] Error at '@': Unknown token"),
+        (
+            "print \"a\nb",
+            "[line 2] Error: Unexpected EOF in unterminated string",
+        ),
+    ];
+    for (source, message) in cases.iter() {
+        let error = rlox::run_no_panic(source, &Options::default()).unwrap_err();
+        assert_eq!(error.to_string(), *message);
+        let error: Box<dyn std::error::Error> = Box::new(error);
+        assert_eq!(error.source().unwrap().to_string(), *message);
+    }
+}
+
+#[test]
+fn a_trailing_expression_is_the_result() {
+    let mut interpreter = Interpreter::new(Options::default());
+    let value = run(&mut interpreter, "var x = 2; x * 21;").unwrap();
+    assert!(matches!(value, Some(Value::Int(42))));
+    assert!(run(&mut interpreter, "x;\nvar y = x;").unwrap().is_none());
+    assert!(run(&mut interpreter, "print x;").unwrap().is_none());
+    assert!(matches!(
+        rlox::run_no_panic("\"a\" + \"b\";", &Options::default()),
+        Ok(Some(Value::String(ref s))) if &**s == "ab"
+    ));
+}
+
+/// Records every hook call, shared with the test like `Buffer`
+#[derive(Clone, Default)]
+struct Trace(Arc<Mutex<Vec<String>>>);
+
+impl Hooks for Trace {
+    fn on_statement(&mut self, stmt: &Stmt) {
+        self.0.lock().unwrap().push(format!("statement {}", stmt));
+    }
+
+    fn on_call(&mut self, function: &dyn Callable, arguments: &[Value], loc: &Loc) {
+        let arguments: Vec<String> = arguments.iter().cloned().map(stringify).collect();
+        self.0.lock().unwrap().push(format!(
+            "call {}({}) at {}",
+            function.name(),
+            arguments.join(", "),
+            loc
+        ));
+    }
+
+    fn on_return(&mut self, function: &dyn Callable, result: Option<&Value>) {
+        let result = result.cloned().map_or(String::from("error"), stringify);
+        self.0
+            .lock()
+            .unwrap()
+            .push(format!("return {} {}", function.name(), result));
+    }
+}
+
+#[test]
+fn hooks_see_statements_calls_and_returns() {
+    let trace = Trace::default();
+    let mut interpreter = Interpreter::new(Options::default()).with_hooks(trace.clone());
+    let source = "var n = len(str(12));\nassert n == 2;\nnumber(nil);";
+    assert!(run(&mut interpreter, source).is_err());
+    assert_eq!(
+        *trace.0.lock().unwrap(),
+        [
+            "statement (var n (call len (call str 12)))",
+            "call str(12) at line 1",
+            "return str 12",
+            "call len(12) at line 1",
+            "return len 2",
+            "statement (assert (== n 2))",
+            "statement (call number nil)",
+            "call number(nil) at line 3",
+            "return number error",
+        ]
+    );
+}
+
+#[test]
+fn runs_stop_at_their_step_and_time_limits() {
+    // A variable keeps the optimizer from folding the chain into a single literal
+    let chain = vec!["one"; 1000].join(" + ");
+    let options = Options {
+        max_steps: Some(1000),
+        ..Options::default()
+    };
+    let mut interpreter = Interpreter::new(options);
+    run(&mut interpreter, "var one = 1;").unwrap();
+    assert!(matches!(
+        run(&mut interpreter, &format!("{};", chain)),
+        Err(Halt::Limit(Limit::Steps(1000)))
+    ));
+    // The budget is per run, and what ran before the limit stays defined
+    assert!(run(&mut interpreter, "var x = 1 + 2; 1 + 1;").is_ok());
+    assert!(run(&mut interpreter, &format!("var y = 1; print {};", chain)).is_err());
+    run(&mut interpreter, "assert x == 3; assert y == 1;").unwrap();
+
+    let options = Options {
+        timeout: Some(Duration::from_millis(10)),
+        ..Options::default()
+    };
+    let mut interpreter = Interpreter::new(options);
+    assert!(matches!(
+        run(&mut interpreter, "sleep(50); print 1;"),
+        Err(Halt::Limit(Limit::Time(_)))
+    ));
+    assert!(run(&mut interpreter, "1 + 1;").is_ok());
+}
+
+#[test]
+fn sleep_wakes_up_at_the_time_limit() {
+    let stdout = Buffer::default();
+    let options = Options {
+        timeout: Some(Duration::from_millis(20)),
+        ..Options::default()
+    };
+    let mut interpreter = Interpreter::new(options).with_stdout(stdout.clone());
+    let started = Instant::now();
+    assert!(matches!(
+        run(&mut interpreter, "print sleep(1e12);"),
+        Err(Halt::Limit(Limit::Time(_)))
+    ));
+    assert!(started.elapsed() < Duration::from_secs(5));
+    assert_eq!(stdout.contents(), "");
+}
+
+#[test]
+fn values_stop_growing_at_the_memory_limit() {
+    let options = Options {
+        max_memory: Some(1 << 20),
+        ..Options::default()
+    };
+    let mut interpreter = Interpreter::new(options);
+    run(&mut interpreter, "var s = \"0123456789abcdef\";").unwrap();
+    let mut doublings = 0;
+    let halt = loop {
+        match run(&mut interpreter, "var s = s + s;") {
+            Ok(_) => doublings += 1,
+            Err(halt) => break halt,
+        }
+    };
+    assert!(matches!(halt, Halt::Limit(Limit::Memory(_))));
+    assert_eq!(doublings, 15);
+    // Replacing a global frees what it held
+    run(&mut interpreter, "var s = nil; var t = [1, 2, 3];").unwrap();
+    assert!(run(&mut interpreter, "var big = [t, t, t];").is_ok());
+    let mut unlimited = Interpreter::new(Options::default());
+    run(&mut unlimited, "var s = \"0123456789abcdef\";").unwrap();
+    for _ in 0..17 {
+        run(&mut unlimited, "var s = s + s;").unwrap();
+    }
+}
+
+#[test]
+fn numbers_print_like_jlox() {
+    let cases = [
+        (Value::Number(2.0), "2"),
+        (Value::Number(-0.0), "-0"),
+        (Value::Number(3.5), "3.5"),
+        (Value::Number(0.1 + 0.2), "0.30000000000000004"),
+        (Value::Number(0.001), "0.001"),
+        (Value::Number(9999999.5), "9999999.5"),
+        (Value::Number(1e7), "1.0E7"),
+        (Value::Number(2f64.powi(70)), "1.1805916207174113E21"),
+        (Value::Number(-2.5e-4), "-2.5E-4"),
+        (Value::Number(f64::INFINITY), "Infinity"),
+        (Value::Number(f64::NEG_INFINITY), "-Infinity"),
+        (Value::Number(f64::NAN), "NaN"),
+        (Value::Int(10_000_000), "10000000"),
+    ];
+    for (value, printed) in cases.iter() {
+        assert_eq!(stringify(value.clone()), *printed);
+    }
+}
+
+#[test]
+fn saved_globals_restore_into_a_new_interpreter() {
+    let mut interpreter = Interpreter::new(Options::default());
+    run(
+        &mut interpreter,
+        "var n = 42; var x = -0.0; var nan = 0 / 0; var s = \"say \\\"hi\\\"\nbye\"; \
+         var nested = [[], [1, [nil, true]], {}]; var m = {1: \"one\", 2.5: [false], \"k\": {}}; \
+         var r = 1..=3; var f = len; var pi = 3;",
+    )
+    .unwrap();
+    let mut saved = vec![];
+    interpreter.save(&mut saved).unwrap();
+
+    let mut restored = Interpreter::new(Options::default());
+    restored.restore(saved.as_slice()).unwrap();
+    run(
+        &mut restored,
+        "assert n == 42; assert str(x) == \"-0\"; assert nan != nan; \
+         assert s == \"say \\\"hi\\\"\nbye\"; assert nested == [[], [1, [nil, true]], {}]; \
+         assert m == {1: \"one\", 2.5: [false], \"k\": {}}; assert r == 1..=3; \
+         assert f(\"abc\") == 3; assert pi == 3;",
+    )
+    .unwrap();
+    // Saving again gives the same bytes, builtins left out
+    let mut resaved = vec![];
+    restored.save(&mut resaved).unwrap();
+    assert_eq!(saved, resaved);
+    assert!(!String::from_utf8(saved).unwrap().contains("clock"));
+}
+
+#[test]
+fn invalid_snapshots_define_nothing() {
+    let cases: [&[u8]; 4] = [
+        b"",
+        b"rlox snapshot 1\ns1:x i1;\ns1:y i2",
+        b"rlox snapshot 1\ns1:x l2;i1;\n",
+        b"rlox snapshot 1\ns1:f F7:nothing\n",
+    ];
+    for snapshot in cases.iter() {
+        let mut interpreter = Interpreter::new(Options::default());
+        let error = interpreter.restore(*snapshot).unwrap_err();
+        assert!(
+            error.to_string().starts_with("Invalid snapshot: "),
+            "{}",
+            error
+        );
+        assert!(run(&mut interpreter, "x;").is_err());
+    }
+}
+
+#[test]
+fn interpreters_on_separate_threads_share_nothing() {
+    let threads: Vec<_> = (0..8)
+        .map(|n| {
+            let stdout = Buffer::default();
+            let mut interpreter = Interpreter::new(Options {
+                max_steps: Some(100_000),
+                ..Options::default()
+            })
+            .with_stdout(stdout.clone());
+            let handle = std::thread::spawn(move || {
+                run(&mut interpreter, &format!("var n = {}; seed(n);", n)).unwrap();
+                for _ in 0..100 {
+                    run(&mut interpreter, "var n = n + 1; print randomInt(0, 9);").unwrap();
+                }
+                run(&mut interpreter, "print n; help(n);").unwrap();
+            });
+            (n, stdout, handle)
+        })
+        .collect();
+    for (n, stdout, handle) in threads {
+        handle.join().unwrap();
+        let mut expected = Interpreter::new(Options::default()).with_stdout(Buffer::default());
+        run(&mut expected, &format!("var n = {}; seed(n);", n)).unwrap();
+        let lines: Vec<String> = stdout.contents().lines().map(String::from).collect();
+        assert_eq!(lines.len(), 102);
+        assert_eq!(lines[100], (n + 100).to_string());
+        assert_eq!(lines[101], format!("{}: a number value", n + 100));
+        for line in &lines[..100] {
+            let draw = run(&mut expected, "randomInt(0, 9);").unwrap();
+            assert_eq!(*line, stringify(draw.unwrap()));
+        }
+    }
+}
+
+#[test]
+fn plus_errors_list_the_operands_it_accepts() {
+    let cases = [
+        (
+            Options::default(),
+            "1 + nil;",
+            "Operator + expects two numbers, or a string and a string or number",
+        ),
+        (
+            Options {
+                strict_concat: true,
+                ..Options::default()
+            },
+            "\"a\" + 1;",
+            "Operator + expects two numbers, or a string and a string",
+        ),
+    ];
+    for (options, source, message) in cases.iter() {
+        let mut interpreter = Interpreter::new(options.clone());
+        let error = run(&mut interpreter, source).unwrap_err();
+        assert_eq!(error.to_string(), format!("[line 1] Error: {}", message));
+    }
+}
+
+#[test]
+fn restored_builtins_are_the_interpreters_own() {
+    let mut interpreter = Interpreter::new(Options::default());
+    run(&mut interpreter, "var r = random; var sorter = sort;").unwrap();
+    let mut saved = vec![];
+    interpreter.save(&mut saved).unwrap();
+
+    let mut restored = Interpreter::new(Options::default());
+    restored.restore(saved.as_slice()).unwrap();
+    run(
+        &mut restored,
+        "assert id(r) == id(random); assert r == random; assert id(sorter) == id(sort); \
+         seed(7); var first = r(); seed(7); assert random() == first;",
+    )
+    .unwrap();
+}
+
+#[test]
+fn conditionals_nest_to_the_right_and_evaluate_one_branch() {
+    assert_eq!(evaluate("true ? 1 : true ? 2 : 3;"), "Int(1)");
+    assert_eq!(evaluate("false ? 1 : true ? 2 : 3;"), "Int(2)");
+    assert_eq!(evaluate("false ? 1 : false ? 2 : 3;"), "Int(3)");
+    assert_eq!(evaluate("nil ? undefined : 0 ? 4 : undefined;"), "Int(4)");
+}
+
+#[test]
+fn remainders_take_the_sign_of_the_dividend() {
+    let cases = [
+        ("7 % 3;", "Int(1)"),
+        ("-7 % 3;", "Int(-1)"),
+        ("7 % -3;", "Int(1)"),
+        ("-7 % -3;", "Int(-1)"),
+        ("7.5 % 2;", "Number(1.5)"),
+        ("-7.5 % 2;", "Number(-1.5)"),
+        ("1 % 0;", "Number(NaN)"),
+        ("1 + 7 % 4 * 2;", "Int(7)"),
+    ];
+    for (source, value) in cases.iter() {
+        assert_eq!(evaluate(source), *value, "{}", source);
+    }
+    assert_eq!(
+        error_with(Options::default(), "\"7\" % 2;"),
+        "[line 1] Error: Binary operator % expects two numeric operands"
+    );
+}
+
+#[test]
+fn powers_nest_to_the_right() {
+    let cases = [
+        ("2 ** 3 ** 2;", "Int(512)"),
+        ("(2 ** 3) ** 2;", "Int(64)"),
+        ("-2 ** 2;", "Int(-4)"),
+        ("2 ** -1;", "Number(0.5)"),
+        ("4 ** 0.5;", "Number(2.0)"),
+        ("(-2) ** 63;", "Int(-9223372036854775808)"),
+        ("2 ** 63;", "Number(9.223372036854776e18)"),
+    ];
+    for (source, value) in cases.iter() {
+        assert_eq!(evaluate(source), *value, "{}", source);
+    }
+}
+
+#[test]
+fn block_comments_nest_and_count_lines() {
+    assert_eq!(evaluate("1 /* one */ + /* /* two */ */ 2;"), "Int(3)");
+    assert_eq!(evaluate("/* 1; /* 2; */ 3; */ 4;"), "Int(4)");
+    let cases = [
+        (
+            "/* one\ntwo */\nprint -nil;",
+            "[line 3] Error: Unary operator - expects a numeric operand",
+        ),
+        (
+            "/* /* */\n*/ /*\n\n",
+            "[line 4] Error: Unexpected EOF in unterminated block comment",
+        ),
+    ];
+    for (source, message) in cases.iter() {
+        let error = rlox::run_no_panic(source, &Options::default()).unwrap_err();
+        assert_eq!(error.to_string(), *message);
+    }
+}
+
+#[test]
+fn bitwise_operators_truncate_to_integers() {
+    let cases = [
+        ("6 & 3;", "Int(2)"),
+        ("6 | 3;", "Int(7)"),
+        ("6 ^ 3;", "Int(5)"),
+        ("~5;", "Int(-6)"),
+        ("5.9 & 3;", "Int(1)"),
+        ("-5.9 | 0;", "Int(-5)"),
+        ("~1.5;", "Int(-2)"),
+        ("1 << 63;", "Int(-9223372036854775808)"),
+        ("-8 >> 1;", "Int(-4)"),
+        ("1 << 2 + 1;", "Int(8)"),
+        ("5 | 3 ^ 6 & 3;", "Int(5)"),
+        ("1 | 2 == 3;", "Bool(true)"),
+    ];
+    for (source, value) in cases.iter() {
+        assert_eq!(evaluate(source), *value, "{}", source);
+    }
+    let cases = [
+        (
+            "1 << 64;",
+            "Operator << expects a shift amount between 0 and 63, got 64",
+        ),
+        (
+            "1 >> -1;",
+            "Operator >> expects a shift amount between 0 and 63, got -1",
+        ),
+        (
+            "1 << 64.5;",
+            "Operator << expects a shift amount between 0 and 63, got 64",
+        ),
+        (
+            "\"1\" & 1;",
+            "Binary operator & expects two numeric operands",
+        ),
+        ("~nil;", "Unary operator ~ expects a numeric operand"),
+    ];
+    for (source, message) in cases.iter() {
+        let error = error_with(Options::default(), source);
+        assert_eq!(error, format!("[line 1] Error: {}", message));
+    }
+}
+
+#[test]
+fn integers_become_floats_only_when_they_must() {
+    let cases = [
+        ("1 + 2;", "Int(3)"),
+        ("1 + 2.0;", "Number(3.0)"),
+        ("6 / 3;", "Number(2.0)"),
+        ("7 / 2;", "Number(3.5)"),
+        ("9223372036854775807;", "Int(9223372036854775807)"),
+        ("9223372036854775808;", "Number(9.223372036854776e18)"),
+        ("9223372036854775807 + 1;", "Number(9.223372036854776e18)"),
+        ("-9223372036854775807 - 2;", "Number(-9.223372036854776e18)"),
+        ("4611686018427387904 * 2;", "Number(9.223372036854776e18)"),
+        (
+            "-(-9223372036854775807 - 1);",
+            "Number(9.223372036854776e18)",
+        ),
+        ("(-9223372036854775807 - 1) % -1;", "Int(0)"),
+        ("1 == 1.0;", "Bool(true)"),
+        ("9007199254740993 == 9007199254740992.0;", "Bool(false)"),
+        ("9007199254740993 > 9007199254740992.0;", "Bool(false)"),
+    ];
+    for (source, value) in cases.iter() {
+        assert_eq!(evaluate(source), *value, "{}", source);
+    }
+}
+
+#[test]
+fn lists_index_from_zero_within_bounds() {
+    assert_eq!(evaluate("[1, 2, 3][2];"), "Int(3)");
+    assert_eq!(evaluate("[[1, 2], [3]][0][1];"), "Int(2)");
+    assert_eq!(evaluate("(1..=3)[2];"), "Int(3)");
+    let cases = [
+        ("[1][1];", "List index 1 out of bounds for length 1"),
+        ("[1][-1];", "List index -1 out of bounds for length 1"),
+        ("[][0];", "List index 0 out of bounds for length 0"),
+        ("[1][0.5];", "List index must be an integer, got 0.5"),
+        ("[1][\"0\"];", "List index must be an integer, got 0"),
+        ("(0..2)[2];", "Range index 2 out of bounds for length 2"),
+        ("1[0];", "Only lists, maps and ranges can be indexed, got 1"),
+    ];
+    for (source, message) in cases.iter() {
+        let error = error_with(Options::default(), source);
+        assert_eq!(error, format!("[line 1] Error: {}", message));
+    }
+}
+
+#[test]
+fn integral_float_keys_are_the_same_as_integer_keys() {
+    let cases = [
+        ("{1: \"a\"}[1.0];", "String(\"a\")"),
+        ("{1.0: \"a\"}[1];", "String(\"a\")"),
+        ("{2.5: \"b\"}[2.5];", "String(\"b\")"),
+        ("{1: \"a\", 1.0: \"b\"}[1];", "String(\"b\")"),
+        ("{\"1\": \"s\"}[1];", "Nil"),
+        ("{}[\"missing\"];", "Nil"),
+        ("len({1: 1, 1.0: 2, \"1\": 3});", "Int(2)"),
+    ];
+    for (source, value) in cases.iter() {
+        assert_eq!(evaluate(source), *value, "{}", source);
+    }
+    let mut interpreter = Interpreter::new(Options::default());
+    let map = run(&mut interpreter, "{2.0: 1, 0.5: [nil], \"k\": {}};").unwrap();
+    assert_eq!(stringify(map.unwrap()), "{0.5: [nil], 2: 1, k: {}}");
+
+    let cases = [
+        ("{}[nil];", "nil"),
+        ("{}[0 / 0];", "NaN"),
+        ("{}[[1]];", "[1]"),
+        ("{true: 1};", "true"),
+    ];
+    for (source, key) in cases.iter() {
+        let error = error_with(Options::default(), source);
+        assert_eq!(
+            error,
+            format!(
+                "[line 1] Error: Map keys must be strings or numbers (and not NaN), got {}",
+                key
+            )
+        );
+    }
+}
+
+#[test]
+fn check_arith_reports_each_float_anomaly() {
+    let check = Options {
+        check_arith: true,
+        ..Options::default()
+    };
+    let cases = [
+        ("0 / 0;", "produced NaN"),
+        ("1e308 * 10;", "overflowed to infinity"),
+        ("1e-300 * 1e-300;", "underflowed to zero"),
+        (
+            "9223372036854775807 + 1;",
+            "overflowed the integer range and lost precision",
+        ),
+        (
+            "2 ** 64;",
+            "overflowed the integer range and lost precision",
+        ),
+        (
+            "0.1 + 0.2 == 0.3;",
+            "compared numbers that differ only by rounding error",
+        ),
+    ];
+    for (source, anomaly) in cases.iter() {
+        let error = error_with(check.clone(), source);
+        assert!(
+            error.starts_with("[line 1] Error: Arithmetic check failed: ")
+                && error.ends_with(anomaly),
+            "{} gave {}",
+            source,
+            error
+        );
+        let mut unchecked = Interpreter::new(Options::default());
+        run(&mut unchecked, source).unwrap();
+    }
+    let mut interpreter = Interpreter::new(check);
+    run(
+        &mut interpreter,
+        "assert 7 / 2 == 3.5; assert 2 ** -1 == 0.5; assert 0.5 + 0.25 == 0.75; \
+         assert 1e-300 / 10 > 0; assert 9223372036854775806 + 1 == 9223372036854775807;",
+    )
+    .unwrap();
+}
+
+#[test]
+fn number_literals_take_radix_prefixes_and_exponents() {
+    let cases = [
+        ("0xFF;", "Int(255)"),
+        ("0Xff;", "Int(255)"),
+        ("0b1010;", "Int(10)"),
+        ("0B11;", "Int(3)"),
+        ("0x7fffffffffffffff;", "Int(9223372036854775807)"),
+        ("0xffffffffffffffff;", "Number(1.8446744073709552e19)"),
+        ("1.5e-3;", "Number(0.0015)"),
+        ("1e3;", "Number(1000.0)"),
+        ("2E+2;", "Number(200.0)"),
+        ("0.5;", "Number(0.5)"),
+        ("42;", "Int(42)"),
+    ];
+    for (source, value) in cases.iter() {
+        assert_eq!(evaluate(source), *value, "{}", source);
+    }
+    let cases = [
+        (
+            "0b102;",
+            "[line 1] Error at '0b102': Invalid digits in base 2 number literal",
+        ),
+        (
+            "0x;",
+            "[line 1] Error at '0x': Invalid digits in base 16 number literal",
+        ),
+        (
+            "0xG;",
+            "[line 1] Error at '0xG': Invalid digits in base 16 number literal",
+        ),
+        (
+            "0x1ffffffffffffffffffffffffffffffff;",
+            "[line 1] Error at '0x1ffffffffffffffffffffffffffffffff': Base 16 number literal too large",
+        ),
+        ("1e400;", "[line 1] Error at '1e400': Number literal too large"),
+    ];
+    for (source, message) in cases.iter() {
+        let error = rlox::run_no_panic(source, &Options::default()).unwrap_err();
+        assert_eq!(error.to_string(), *message);
+    }
+}
+
+#[test]
+fn nil_coalescing_skips_only_nil() {
+    let cases = [
+        ("nil ?? 1;", "Int(1)"),
+        ("false ?? 1;", "Bool(false)"),
+        ("0 ?? 1;", "Int(0)"),
+        ("nil ?? nil ?? 3;", "Int(3)"),
+        ("1 ?? undefined;", "Int(1)"),
+    ];
+    for (source, value) in cases.iter() {
+        assert_eq!(evaluate(source), *value, "{}", source);
+    }
+}
+
+#[test]
+fn only_input_cut_short_counts_as_unfinished() {
+    for (source, unfinished) in [("\"abc", true), ("/* a", true), ("@", false)].iter() {
+        let error = Scanner::new(source).scan().unwrap_err();
+        assert_eq!(error.is_unexpected_eof(), *unfinished, "{}", source);
+    }
+    for (source, unfinished) in [
+        ("print 1", true),
+        ("var x = (1 +", true),
+        ("print );", false),
+    ]
+    .iter()
+    {
+        let tokens = Scanner::new(source).scan().unwrap();
+        let error = parse(&tokens).unwrap_err();
+        assert_eq!(error.is_unexpected_eof(), *unfinished, "{}", source);
+    }
+}
@@ -0,0 +1,376 @@
+use std::collections::BTreeMap;
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use rlox::callable::{CallError, Callable, NativeFunction};
+use rlox::environment::Environment;
+use rlox::interpreter::{Deadline, Options, Output};
+use rlox::natives::natives;
+use rlox::value::{lox_eq, MapKey, Value};
+
+fn call(name: &str, arguments: Vec<Value>) -> Result<Value, CallError> {
+    let native = natives(&Options::default(), &Output::stdout(), &Deadline::default())
+        .into_iter()
+        .find(|native| native.name() == name)
+        .unwrap_or_else(|| panic!("no native named {}", name));
+    assert!(
+        native.arity().contains(&arguments.len()),
+        "arity of {}",
+        name
+    );
+    native.call(arguments)
+}
+
+fn int(n: i64) -> Value {
+    Value::Int(n)
+}
+
+fn string(s: &str) -> Value {
+    Value::String(s.into())
+}
+
+fn assert_returns(name: &str, arguments: Vec<Value>, expected: Value) {
+    let result = call(name, arguments).unwrap();
+    assert!(
+        lox_eq(&result, &expected),
+        "{} returned {:?}, expected {:?}",
+        name,
+        result,
+        expected
+    );
+}
+
+#[test]
+#[cfg(feature = "unicode")]
+fn string_functions_count_graphemes() {
+    assert_returns("len", vec![string("naïve 👍🏽")], int(7));
+    assert_returns(
+        "substr",
+        vec![string("👍🏽a👍🏽b"), int(1), int(2)],
+        string("a👍🏽"),
+    );
+    assert_returns("indexOf", vec![string("👍🏽a"), string("a")], int(1));
+    assert_returns(
+        "split",
+        vec![string("a👍🏽b"), string("")],
+        Value::List(Arc::new(vec![string("a"), string("👍🏽"), string("b")])),
+    );
+}
+
+#[test]
+fn string_functions_work_on_characters() {
+    assert_returns("len", vec![string("naïve")], int(5));
+    assert_returns(
+        "substr",
+        vec![string("héllo"), int(1), int(9)],
+        string("éllo"),
+    );
+    assert_returns("indexOf", vec![string("a"), string("b")], int(-1));
+    assert_returns(
+        "contains",
+        vec![string("héllo"), string("él")],
+        Value::Bool(true),
+    );
+    assert_returns(
+        "split",
+        vec![string("a,,b"), string(",")],
+        Value::List(Arc::new(vec![string("a"), string(""), string("b")])),
+    );
+}
+
+#[test]
+fn string_functions_reject_other_types() {
+    assert!(call("upper", vec![int(1)]).is_err());
+    assert!(call("substr", vec![string("abc"), int(4), int(1)]).is_err());
+    assert!(call("substr", vec![string("abc"), int(0), int(-1)]).is_err());
+}
+
+#[test]
+fn math_functions_keep_integers_exact() {
+    assert_returns("abs", vec![int(-3)], int(3));
+    assert_returns("floor", vec![Value::Number(-2.5)], int(-3));
+    assert_returns("round", vec![int(i64::MAX)], int(i64::MAX));
+    assert_returns("max", vec![int(i64::MAX), int(i64::MAX - 1)], int(i64::MAX));
+    assert_returns("pow", vec![int(3), int(39)], int(4052555153018976267));
+    assert!(matches!(
+        call("pow", vec![int(2), int(-1)]),
+        Ok(Value::Number(n)) if n == 0.5
+    ));
+}
+
+#[test]
+fn seeding_makes_random_numbers_repeatable() {
+    let natives = natives(&Options::default(), &Output::stdout(), &Deadline::default());
+    let native = |name: &str| natives.iter().find(|native| native.name() == name).unwrap();
+    let draw = || {
+        native("seed").call(vec![int(7)]).unwrap();
+        let x = native("random").call(vec![]).unwrap();
+        let n = native("randomInt").call(vec![int(-3), int(3)]).unwrap();
+        (x, n)
+    };
+    let (x, n) = draw();
+    assert!(matches!(x, Value::Number(x) if (0.0..1.0).contains(&x)));
+    assert!(matches!(n, Value::Int(n) if (-3..=3).contains(&n)));
+    let (y, m) = draw();
+    assert!(lox_eq(&x, &y) && lox_eq(&n, &m));
+}
+
+#[test]
+fn map_functions_tell_nil_entries_from_missing_ones() {
+    let map = || {
+        Value::Map(Arc::new(BTreeMap::from([(
+            MapKey::String(String::from("a")),
+            Value::Nil,
+        )])))
+    };
+    let bool = |b| Value::Bool(b);
+    assert_returns("has", vec![map(), string("a")], bool(true));
+    assert_returns("has", vec![map(), string("b")], bool(false));
+    assert_returns(
+        "keys",
+        vec![map()],
+        Value::List(Arc::new(vec![string("a")])),
+    );
+    assert_returns(
+        "remove",
+        vec![map(), string("a")],
+        Value::Map(Arc::new(BTreeMap::new())),
+    );
+}
+
+#[test]
+fn exit_unwinds_with_its_status() {
+    assert!(matches!(
+        call("exit", vec![int(3)]),
+        Err(CallError::Exit(3))
+    ));
+    assert!(matches!(
+        call("exit", vec![int(1 << 40)]),
+        Err(CallError::Error(_))
+    ));
+}
+
+#[test]
+fn error_fails_with_its_message() {
+    match call("error", vec![string("bad input")]) {
+        Err(CallError::Error(message)) => assert_eq!(message, "bad input"),
+        other => panic!("error() returned {:?}", other),
+    }
+}
+
+#[test]
+fn sleep_stops_when_interrupted() {
+    let interrupt = Arc::new(AtomicBool::new(true));
+    let options = Options {
+        interrupt: Some(interrupt),
+        ..Options::default()
+    };
+    let sleep = natives(&options, &Output::stdout(), &Deadline::default())
+        .into_iter()
+        .find(|native| native.name() == "sleep")
+        .unwrap();
+    let started = Instant::now();
+    assert!(matches!(
+        sleep.call(vec![int(60_000)]),
+        Err(CallError::Error(_))
+    ));
+    assert!(started.elapsed() < Duration::from_secs(10));
+}
+
+#[test]
+fn json_round_trips_through_lox_values() {
+    let text = r#"{"a": [1, 2.5, -3e2, true, null], "b": {"c": "\u00e9\ud83d\ude00\n"}}"#;
+    let parsed = call("jsonParse", vec![string(text)]).unwrap();
+    let expected = Value::Map(Arc::new(BTreeMap::from([
+        (
+            MapKey::String(String::from("a")),
+            Value::List(Arc::new(vec![
+                int(1),
+                Value::Number(2.5),
+                Value::Number(-300.0),
+                Value::Bool(true),
+                Value::Nil,
+            ])),
+        ),
+        (
+            MapKey::String(String::from("b")),
+            Value::Map(Arc::new(BTreeMap::from([(
+                MapKey::String(String::from("c")),
+                string("é😀\n"),
+            )]))),
+        ),
+    ])));
+    assert!(lox_eq(&parsed, &expected), "parsed {:?}", parsed);
+
+    assert_returns(
+        "jsonStringify",
+        vec![parsed.clone()],
+        string(r#"{"a":[1,2.5,-300.0,true,null],"b":{"c":"é😀\n"}}"#),
+    );
+    assert_returns(
+        "jsonStringify",
+        vec![parsed, Value::Bool(true)],
+        string("{\n  \"a\": [\n    1,\n    2.5,\n    -300.0,\n    true,\n    null\n  ],\n  \"b\": {\n    \"c\": \"é😀\\n\"\n  }\n}"),
+    );
+
+    for malformed in &["", "[1,]", "{\"a\" 1}", "01", "\"\\ud800\"", "nul", "[] []"] {
+        assert!(
+            call("jsonParse", vec![string(malformed)]).is_err(),
+            "parsed {}",
+            malformed
+        );
+    }
+    let deep = "[".repeat(1000);
+    assert!(call("jsonParse", vec![string(&deep)]).is_err());
+    assert!(call("jsonStringify", vec![Value::Number(f64::NAN)]).is_err());
+}
+
+#[test]
+fn format_fills_placeholders_in_order_or_by_index() {
+    assert_returns(
+        "format",
+        vec![
+            string("x = {}, y = {:.2}, {{{}}}"),
+            int(1),
+            Value::Number(2.0 / 3.0),
+            string("z"),
+        ],
+        string("x = 1, y = 0.67, {z}"),
+    );
+    assert_returns(
+        "format",
+        vec![
+            string("[{1:>4}|{0:<4}|{0:^5}|{1:4.1}]"),
+            string("ab"),
+            int(7),
+        ],
+        string("[   7|ab  | ab  | 7.0]"),
+    );
+    for bad in &["{", "}", "{:.}", "{2}", "{:.1}"] {
+        assert!(
+            call("format", vec![string(bad), string("s")]).is_err(),
+            "formatted {}",
+            bad
+        );
+    }
+}
+
+#[test]
+fn script_arguments_are_the_global_args() {
+    let options = Options {
+        args: vec![String::from("a"), String::from("b c")],
+        ..Options::default()
+    };
+    let globals = Environment::with_natives(&options, &Output::stdout(), &Deadline::default());
+    let expected = Value::List(Arc::new(vec![string("a"), string("b c")]));
+    assert!(lox_eq(globals.get("args").unwrap(), &expected));
+}
+
+#[test]
+fn sort_orders_values_or_asks_the_comparator() {
+    let list = |elements: Vec<Value>| Value::List(Arc::new(elements));
+    assert_returns(
+        "sort",
+        vec![list(vec![
+            string("b"),
+            int(3),
+            Value::Nil,
+            Value::Number(1.5),
+        ])],
+        list(vec![Value::Nil, Value::Number(1.5), int(3), string("b")]),
+    );
+
+    let by_length_descending = NativeFunction::new("byLength", &["a", "b"], "", |arguments| {
+        let len = |value: &Value| match value {
+            Value::String(s) => s.len() as i64,
+            _ => 0,
+        };
+        Ok(int(len(&arguments[1]) - len(&arguments[0])))
+    });
+    let comparator = Value::Function(Arc::new(by_length_descending));
+    assert_returns(
+        "sort",
+        vec![
+            list(vec![string("a"), string("ccc"), string("b"), string("dd")]),
+            comparator,
+        ],
+        list(vec![string("ccc"), string("dd"), string("a"), string("b")]),
+    );
+
+    let broken = NativeFunction::new("broken", &["a", "b"], "", |_| Ok(string("?")));
+    let comparator = Value::Function(Arc::new(broken));
+    assert!(call("sort", vec![list(vec![int(1), int(2)]), comparator]).is_err());
+
+    let random = natives(&Options::default(), &Output::stdout(), &Deadline::default())
+        .into_iter()
+        .find(|native| native.name() == "random")
+        .unwrap();
+    for elements in [vec![], vec![int(1), int(2)]].iter() {
+        match call(
+            "sort",
+            vec![list(elements.clone()), Value::Function(Arc::clone(&random))],
+        ) {
+            Err(CallError::Error(message)) => {
+                assert_eq!(message, "random() expects 0 arguments, got 2")
+            }
+            other => panic!("sort() with random() returned {:?}", other),
+        }
+    }
+}
+
+#[test]
+fn hash_agrees_with_equality_and_id_tells_functions_apart() {
+    let hash = |value: Value| match call("hash", vec![value]) {
+        Ok(Value::Int(hash)) => hash,
+        other => panic!("hash() returned {:?}", other),
+    };
+    assert_eq!(hash(int(2)), hash(Value::Number(2.0)));
+    assert_eq!(hash(string("a")), hash(string("a")));
+
+    let natives = natives(&Options::default(), &Output::stdout(), &Deadline::default());
+    let id = |function: &Arc<dyn Callable>| {
+        call("id", vec![Value::Function(Arc::clone(function))]).unwrap()
+    };
+    assert!(lox_eq(&id(&natives[0]), &id(&natives[0])));
+    assert!(!lox_eq(&id(&natives[0]), &id(&natives[1])));
+    assert!(call("id", vec![Value::List(Arc::new(vec![]))]).is_err());
+}
+
+#[test]
+#[cfg(feature = "net")]
+fn http_get_needs_to_be_allowed() {
+    match call("httpGet", vec![string("http://127.0.0.1:1/")]) {
+        Err(CallError::Error(message)) => assert!(message.contains("--allow-net"), "{}", message),
+        other => panic!("httpGet() returned {:?}", other),
+    }
+}
+
+#[test]
+fn map_functions_leave_shared_maps_alone() {
+    let map = Value::Map(Arc::new(BTreeMap::from([(MapKey::Int(1), int(1))])));
+    let other = Value::Map(Arc::new(BTreeMap::from([(MapKey::Int(2), int(2))])));
+    let removed = call("remove", vec![map.clone(), int(1)]).unwrap();
+    let merged = call("merge", vec![map.clone(), other]).unwrap();
+    assert_returns("len", vec![removed], int(0));
+    assert_returns("len", vec![merged], int(2));
+    assert_returns("len", vec![map], int(1));
+}
+
+#[test]
+fn list_functions_leave_shared_lists_alone() {
+    let list = Value::List(Arc::new(vec![int(1), int(2)]));
+    let pushed = call("push", vec![list.clone(), string("a")]).unwrap();
+    let popped = call("pop", vec![list.clone()]).unwrap();
+    assert_returns(
+        "push",
+        vec![list.clone(), Value::Nil],
+        Value::List(Arc::new(vec![int(1), int(2), Value::Nil])),
+    );
+    assert_returns("len", vec![pushed], int(3));
+    assert_returns("len", vec![popped.clone()], int(1));
+    assert_returns("len", vec![list], int(2));
+    assert_returns("pop", vec![popped], Value::List(Arc::new(vec![])));
+    assert!(call("pop", vec![Value::List(Arc::new(vec![]))]).is_err());
+    assert!(call("push", vec![int(1), int(2)]).is_err());
+}
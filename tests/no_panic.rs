@@ -0,0 +1,190 @@
+use rlox::interpreter::Options;
+use rlox::program::{Program, RunError};
+use rlox::run_no_panic;
+
+/// Pieces of Lox source, including the operators and literals most likely to hit edge cases
+const FRAGMENTS: &[&str] = &[
+    "(",
+    ")",
+    "[",
+    "]",
+    "{",
+    "}",
+    ",",
+    ":",
+    ";",
+    "?",
+    "??",
+    "..",
+    "..=",
+    "print",
+    "var",
+    "x",
+    "=",
+    "type",
+    "nil",
+    "true",
+    "false",
+    "0",
+    "1",
+    "2.5",
+    "1e308",
+    "0x7fffffffffffffff",
+    "0b",
+    "0xg",
+    "1.2.3",
+    "-9223372036854775808",
+    "9223372036854775807",
+    "\"a\"",
+    "\"",
+    "+",
+    "-",
+    "*",
+    "/",
+    "%",
+    "**",
+    "!",
+    "~",
+    "&",
+    "|",
+    "^",
+    "<<",
+    ">>",
+    "64",
+    "==",
+    "!=",
+    "<",
+    ">=",
+    "/*",
+    "*/",
+    "//",
+    "\n",
+    " ",
+    "@",
+    "é",
+    "0.0",
+];
+
+/// A small xorshift generator, so failures are reproducible without extra dependencies
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> usize {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0 as usize
+    }
+}
+
+fn assert_no_panic(source: &str) {
+    for options in &[
+        Options::default(),
+        Options {
+            check_arith: true,
+            ..Options::default()
+        },
+    ] {
+        if let Err(RunError::Internal(message)) = run_no_panic(source, options) {
+            panic!("{:?} panicked: {}", source, message);
+        }
+    }
+}
+
+/// Runs `test` on a thread with the stack `run_no_panic` documents for debug builds
+fn with_documented_stack(test: fn()) {
+    std::thread::Builder::new()
+        .stack_size(4 << 20)
+        .spawn(test)
+        .unwrap()
+        .join()
+        .unwrap();
+}
+
+#[test]
+fn random_sources_do_not_panic() {
+    with_documented_stack(|| {
+        let mut rng = Rng(0x2545_f491_4f6c_dd1d);
+        for _ in 0..5000 {
+            let len = rng.next() % 24;
+            let source: Vec<&str> = (0..len)
+                .map(|_| FRAGMENTS[rng.next() % FRAGMENTS.len()])
+                .collect();
+            assert_no_panic(&source.join(" "));
+        }
+    });
+}
+
+#[test]
+fn deep_nesting_is_rejected_without_overflowing() {
+    with_documented_stack(|| {
+        let depth = 100_000;
+        for (open, close) in &[
+            ("(", ")"),
+            ("[", "]"),
+            ("{1: ", "}"),
+            ("type(", ")"),
+            ("-", ""),
+            ("!", ""),
+        ] {
+            assert_no_panic(&format!(
+                "print {}1{};",
+                open.repeat(depth),
+                close.repeat(depth)
+            ));
+        }
+        assert_no_panic(&format!("print {}1;", "2 ** ".repeat(depth)));
+        assert_no_panic(&format!("print 1{};", " ? 1 : 1".repeat(depth)));
+        assert_no_panic(&format!("print [1]{};", "[0]".repeat(depth)));
+        assert_no_panic(&format!("print type{};", "(type)".repeat(depth)));
+    });
+}
+
+#[test]
+fn long_chains_do_not_overflow() {
+    with_documented_stack(|| {
+        let chain = vec!["1"; 100_000];
+        assert_no_panic(&format!("print {};", chain.join(" + ")));
+        assert_no_panic(&format!("print {};", chain.join(" ?? ")));
+        // Fails to parse after building the whole chain, which then has to be dropped
+        assert_no_panic(&format!("print {} +;", chain.join(" - ")));
+    });
+}
+
+#[test]
+fn printing_long_chains_does_not_overflow() {
+    with_documented_stack(|| {
+        // Variables, so the optimizer can't fold the chain away before `--ast` prints it
+        let source = format!("print {};", vec!["x"; 100_000].join(" + "));
+        let program = Program::compile(&source).unwrap();
+        let printed = program.statements()[0].to_string();
+        assert!(
+            printed.starts_with("(print (+ (+ x x) x) x)") || printed.starts_with("(print (+ (+ ")
+        );
+        assert_eq!(printed.matches('x').count(), 100_000);
+    });
+}
+
+#[test]
+fn deeply_nested_values_do_not_overflow() {
+    with_documented_stack(|| {
+        let depth = 200_000;
+        for (nil, nest) in &[("[]", "var a = [a];"), ("{}", "var a = {1: a};")] {
+            let source = format!(
+                "var a = {};\n{}\n\
+                 var b = a;\n\
+                 print len(str(a)) > 0;\n\
+                 print a == b;\n\
+                 print a != [a];\n\
+                 print hash(a) == hash(b);\n\
+                 print len(sort([a, b])) == 2;\n\
+                 print len(jsonStringify(a)) > 0;\n\
+                 print len(a);",
+                nil,
+                nest.repeat(depth)
+            );
+            let result = run_no_panic(&source, &Options::default());
+            assert!(result.is_ok(), "{}: {:?}", nest, result.err());
+        }
+    });
+}
@@ -0,0 +1,52 @@
+use rlox::interpreter::Options;
+use rlox::program::Program;
+
+fn optimized(source: &str) -> Vec<String> {
+    let program = Program::compile(source).unwrap();
+    program
+        .statements()
+        .iter()
+        .map(|stmt| stmt.to_string())
+        .collect()
+}
+
+#[test]
+fn constants_and_dead_branches_are_folded() {
+    assert_eq!(optimized("print 2 * 3 + x;"), ["(print (+ 6 x))"]);
+    assert_eq!(optimized("print x + 2 * 3;"), ["(print (+ x 6))"]);
+    assert_eq!(optimized("var y = -(1 + 2) ** 2;"), ["(var y -9)"]);
+    assert_eq!(optimized("print nil ? x : 1 < 2;"), ["(print true)"]);
+    assert_eq!(optimized("print 0 ?? x;"), ["(print 0)"]);
+    assert_eq!(optimized("print nil ?? x;"), ["(print x)"]);
+}
+
+#[test]
+fn folding_leaves_errors_and_option_dependent_results_alone() {
+    assert_eq!(optimized("print 1 / 0;"), ["(print (/ 1 0))"]);
+    assert_eq!(
+        optimized("print 9223372036854775807 + 1;"),
+        ["(print (+ 9223372036854775807 1))"]
+    );
+    assert_eq!(optimized("print \"a\" + 1;"), ["(print (+ a 1))"]);
+    assert_eq!(optimized("print 1 < nil;"), ["(print (< 1 nil))"]);
+    let strict = Options {
+        strict_concat: true,
+        ..Options::default()
+    };
+    assert!(Program::compile("print \"a\" + 1;")
+        .unwrap()
+        .run(&strict)
+        .is_err());
+}
+
+#[test]
+fn double_negation_is_kept_when_it_can_change_the_type() {
+    assert_eq!(optimized("print - -(x / y);"), ["(print (/ x y))"]);
+    assert_eq!(optimized("print - -(x * y);"), ["(print (- (- (* x y))))"]);
+    let program = Program::compile(
+        "var min = -9223372036854775807 - 1; assert type(- -min) == \"number\"; \
+         assert str(- -min) == \"-9.223372036854776E18\";",
+    )
+    .unwrap();
+    program.run(&Options::default()).unwrap();
+}
@@ -0,0 +1,158 @@
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashSet};
+use std::sync::Arc;
+
+use rlox::callable::{Callable, NativeFunction};
+use rlox::value::{lox_cmp, lox_eq, lox_hash, MapKey, Range, Value, ValueKey};
+
+fn int(n: i64) -> Value {
+    Value::Int(n)
+}
+
+fn float(n: f64) -> Value {
+    Value::Number(n)
+}
+
+fn string(s: &str) -> Value {
+    Value::String(s.into())
+}
+
+#[test]
+fn equality_follows_lox() {
+    assert!(lox_eq(&Value::Nil, &Value::Nil));
+    assert!(lox_eq(&int(1), &float(1.0)));
+    assert!(lox_eq(&float(-0.0), &int(0)));
+    assert!(!lox_eq(&float(f64::NAN), &float(f64::NAN)));
+    assert!(!lox_eq(&int(1), &string("1")));
+    assert!(!lox_eq(&Value::Nil, &Value::Bool(false)));
+    // 2^53 + 1 has no exact float, so it must not equal its rounded neighbour
+    assert!(!lox_eq(&int((1 << 53) + 1), &float((1u64 << 53) as f64)));
+    assert!(lox_eq(
+        &Value::List(Arc::new(vec![int(1), Value::Nil])),
+        &Value::List(Arc::new(vec![float(1.0), Value::Nil]))
+    ));
+}
+
+#[test]
+fn functions_are_equal_only_to_themselves() {
+    let function = |name| {
+        let native: Arc<dyn Callable> =
+            Arc::new(NativeFunction::new(name, &[], "", |_| Ok(Value::Nil)));
+        Value::Function(native)
+    };
+    let f = function("f");
+    let same_name = function("f");
+    assert!(lox_eq(&f, &f.clone()));
+    assert_eq!(lox_cmp(&f, &f.clone()), Ordering::Equal);
+    assert_eq!(lox_hash(&f), lox_hash(&f.clone()));
+    assert!(!lox_eq(&f, &same_name));
+    assert_ne!(lox_cmp(&f, &same_name), Ordering::Equal);
+    assert_ne!(ValueKey(f), ValueKey(same_name));
+}
+
+#[test]
+fn hash_agrees_with_equality() {
+    let pairs = [
+        (int(1), float(1.0)),
+        (int(0), float(-0.0)),
+        (float(f64::NAN), float(-f64::NAN)),
+        (
+            Value::List(Arc::new(vec![int(2), string("a")])),
+            Value::List(Arc::new(vec![float(2.0), string("a")])),
+        ),
+        (
+            Value::Map(Arc::new(BTreeMap::from([(MapKey::Int(1), int(3))]))),
+            Value::Map(Arc::new(BTreeMap::from([(MapKey::Int(1), float(3.0))]))),
+        ),
+    ];
+    for (lhs, rhs) in pairs.iter() {
+        assert_eq!(ValueKey(lhs.clone()), ValueKey(rhs.clone()));
+        assert_eq!(lox_hash(lhs), lox_hash(rhs));
+    }
+    let keys: HashSet<ValueKey> = pairs
+        .iter()
+        .flat_map(|(lhs, rhs)| vec![ValueKey(lhs.clone()), ValueKey(rhs.clone())])
+        .collect();
+    assert_eq!(keys.len(), pairs.len());
+}
+
+#[test]
+fn ordering_groups_types_and_compares_numbers_exactly() {
+    let mut values = vec![
+        string("b"),
+        float(f64::NAN),
+        int(i64::MAX),
+        float(9.3e18),
+        Value::Bool(true),
+        float(-1.5),
+        Value::Nil,
+        string("a"),
+        int(-1),
+        Value::List(Arc::new(vec![])),
+    ];
+    values.sort_by(lox_cmp);
+    let expected = vec![
+        Value::Nil,
+        Value::Bool(true),
+        float(-1.5),
+        int(-1),
+        int(i64::MAX),
+        float(9.3e18),
+        float(f64::NAN),
+        string("a"),
+        string("b"),
+        Value::List(Arc::new(vec![])),
+    ];
+    let keys = |values: Vec<Value>| values.into_iter().map(ValueKey).collect::<Vec<_>>();
+    assert_eq!(keys(values), keys(expected));
+}
+
+fn range(start: i64, end: i64, inclusive: bool) -> Range {
+    Range {
+        start,
+        end,
+        inclusive,
+    }
+}
+
+#[test]
+fn ranges_count_up_or_down() {
+    assert_eq!(range(0, 3, false).iter().collect::<Vec<_>>(), [0, 1, 2]);
+    assert_eq!(range(3, 0, false).iter().collect::<Vec<_>>(), [3, 2, 1]);
+    assert_eq!(range(-1, -3, true).iter().collect::<Vec<_>>(), [-1, -2, -3]);
+    assert!(range(5, 5, false).is_empty());
+    assert_eq!(range(i64::MIN, i64::MAX, true).len(), u64::MAX);
+    assert_eq!(range(i64::MAX, i64::MIN, true).get(1), Some(i64::MAX - 1));
+}
+
+#[test]
+fn ranges_compare_by_elements() {
+    let pairs = [
+        (range(0, 3, false), range(0, 2, true)),
+        (range(1, 1, false), range(7, 7, false)),
+        (range(4, 4, true), range(4, 5, false)),
+    ];
+    for (lhs, rhs) in pairs.iter() {
+        let (lhs, rhs) = (Value::Range(*lhs), Value::Range(*rhs));
+        assert!(lox_eq(&lhs, &rhs));
+        assert_eq!(lox_hash(&lhs), lox_hash(&rhs));
+    }
+    assert!(!lox_eq(
+        &Value::Range(range(0, 2, false)),
+        &Value::Range(range(1, -1, false))
+    ));
+}
+
+#[test]
+fn clones_share_strings_and_collections() {
+    let list = Value::List(Arc::new(vec![string("a"), int(1)]));
+    match (&list, &list.clone()) {
+        (Value::List(lhs), Value::List(rhs)) => assert!(Arc::ptr_eq(lhs, rhs)),
+        _ => unreachable!(),
+    }
+    let s = string("shared");
+    match (&s, &s.clone()) {
+        (Value::String(lhs), Value::String(rhs)) => assert!(Arc::ptr_eq(lhs, rhs)),
+        _ => unreachable!(),
+    }
+}